@@ -0,0 +1,98 @@
+//! Compares recomputing the per-event values against looking them up in an [`EventContext`].
+use bls12_381::pairing;
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use dlc_venc_pairing::{
+    common::{message_for_event_index, verify_bls_sig, EventContext, ParamsBuilder},
+    oracle::Oracle,
+};
+
+const EVENT_ID: &str = "btc-usd-2022-06-01";
+const N_OUTCOMES: u32 = 100;
+
+fn event_context(c: &mut Criterion) {
+    let rng = &mut rand::thread_rng();
+    let oracles = (0..3).map(|_| Oracle::random(rng)).collect::<Vec<_>>();
+    let params = ParamsBuilder::new(
+        oracles.iter().map(Oracle::key).collect(),
+        EVENT_ID,
+        N_OUTCOMES,
+        2,
+    )
+    .cut_and_choose(0.85, 5)
+    .build()
+    .unwrap();
+    let context = params.event_context();
+    let base = params.base();
+    let n_digits = params.n_outcome_digits();
+    let attestation = oracles[0].attest(EVENT_ID, base, n_digits as usize, 42);
+    let public_key = oracles[0].public_key();
+
+    let mut group = c.benchmark_group("anticipations");
+    group.sample_size(10);
+    group.bench_function("recompute", |b| {
+        b.iter(|| {
+            oracles
+                .iter()
+                .map(|oracle| {
+                    (0..n_digits)
+                        .map(|digit_index| {
+                            (0..base as u32)
+                                .map(|digit_value| {
+                                    let message = message_for_event_index(
+                                        EVENT_ID,
+                                        base,
+                                        digit_index,
+                                        digit_value,
+                                    );
+                                    pairing(&oracle.public_key(), &message)
+                                })
+                                .collect::<Vec<_>>()
+                        })
+                        .collect::<Vec<_>>()
+                })
+                .collect::<Vec<_>>()
+        })
+    });
+    group.bench_function("build_context", |b| {
+        b.iter(|| EventContext::new(params.oracle_keys(), EVENT_ID, base, n_digits))
+    });
+    // what Alice and Bob do for each window of commitments once the context is built
+    params.event_context();
+    group.bench_function("lookup", |b| {
+        b.iter(|| {
+            for oracle_index in 0..oracles.len() {
+                for digit_index in 0..n_digits {
+                    for digit_value in 0..base as u32 {
+                        black_box(params.anticipate_at_index(
+                            oracle_index,
+                            digit_index,
+                            digit_value,
+                        ));
+                    }
+                }
+            }
+        })
+    });
+    group.finish();
+
+    let mut group = c.benchmark_group("verify_attestation");
+    group.bench_function("recompute", |b| b.iter(|| attestation.verify()));
+    group.bench_function("cached", |b| {
+        b.iter(|| attestation.verify_with_context(context))
+    });
+    group.finish();
+
+    let mut group = c.benchmark_group("verify_bls_sig");
+    let sig = attestation.digit_signatures[0];
+    let digit_value = attestation.outcome_index % base as u32;
+    group.bench_function("recompute", |b| {
+        b.iter(|| verify_bls_sig(&public_key, EVENT_ID, base, 0, digit_value, &sig))
+    });
+    group.bench_function("cached", |b| {
+        b.iter(|| context.verify_bls_sig(&public_key, 0, digit_value, &sig))
+    });
+    group.finish();
+}
+
+criterion_group!(benches, event_context);
+criterion_main!(benches);
@@ -1,8 +1,21 @@
-use crate::{common::Params, messages::*};
+use crate::{
+    common::Params,
+    encoding::{append_checksum, read_header, strip_checksum, write_header, Reader, Writer},
+    error::{ListKind, ProtocolError},
+    fixed_base::GtTable,
+    messages::*,
+};
 use anyhow::anyhow;
 use bls12_381::{G1Affine, Gt, Scalar};
+use chacha20poly1305::{
+    aead::{Aead, KeyInit, Payload},
+    XChaCha20Poly1305, XNonce,
+};
 use ff::Field;
+use rand::{CryptoRng, RngCore, SeedableRng};
+use rand_chacha::ChaCha20Rng;
 use secp256kfun::{g, marker::*, s, Scalar as ChainScalar, G};
+use sha2::{digest::Digest, Sha256};
 
 pub struct Alice1 {
     commit_secrets: Vec<(ChainScalar, Scalar, Gt)>,
@@ -10,34 +23,35 @@ pub struct Alice1 {
 }
 
 impl Alice1 {
-    pub fn new(params: &Params) -> (Alice1, Message1) {
-        let (commits, commit_secrets): (Vec<Commit>, Vec<(ChainScalar, Scalar, Gt)>) = (0..params
-            .M())
-            .map(|_| {
+    pub fn new(params: &Params, rng: &mut (impl RngCore + CryptoRng)) -> (Alice1, Message1) {
+        let (commits, commit_secrets): (Vec<Commit>, Vec<(ChainScalar, Scalar, Gt)>) =
+            crate::parallel::map(crate::parallel::item_rngs(rng, params.M()), |mut rng| {
+                let rng = &mut rng;
                 // hackily map elements of Z_q to G_t
                 let (hashed_xor_ri, ri, ri_mapped) = {
-                    let ri = ChainScalar::random(&mut rand::thread_rng());
-                    let (ri_mapped, pad) = params.map_Zq_to_Gt(&ri);
+                    let ri = ChainScalar::random(rng);
+                    let (ri_mapped, pad) = params.map_Zq_to_Gt(&ri, rng);
                     (pad, ri, ri_mapped)
                 };
 
                 let Ri = g!(ri * G).normalize();
-                let ri_prime = Scalar::random(&mut rand::thread_rng());
+                let ri_prime = Scalar::random(&mut *rng);
                 // Create Elgamal comitments in the form of (G_1, G_T)
                 let C_i = (
-                    (G1Affine::generator() * &ri_prime).into(),
-                    (&params.elgamal_base * ri_prime) + &ri_mapped,
+                    (G1Affine::generator() * ri_prime).into(),
+                    params.elgamal_base_table().mul(&ri_prime) + ri_mapped,
                 );
 
                 (
                     Commit {
                         C: C_i,
                         R: Ri,
-                        pad: hashed_xor_ri.try_into().unwrap(),
+                        pad: hashed_xor_ri,
                     },
                     (ri, ri_prime, ri_mapped),
                 )
             })
+            .into_iter()
             .unzip();
 
         (
@@ -49,27 +63,193 @@ impl Alice1 {
         )
     }
 
-    pub fn receive_message(
+    /// Like [`Alice1::new`] but all the randomness comes from `seed` so the same seed and `Params`
+    /// always give the same `Alice1` and `Message1`. Instead of storing `Alice1` she can store the
+    /// seed and call this again after a crash.
+    pub fn new_seeded(seed: &[u8; 32], params: &Params) -> (Alice1, Message1) {
+        Self::new(params, &mut seeded_rng(seed, params, b"message1", &[]))
+    }
+
+    /// Like [`Alice1::receive_message`] but all the randomness comes from `seed` and `message` so
+    /// Alice can regenerate the exact same `Message3` after a crash.
+    ///
+    /// Alice must never answer two different `Message2`s for the same `Message1` (seeded or not)
+    /// since that would reveal the secrets of commitments that are closed in one and opened in the
+    /// other.
+    pub fn receive_message_seeded(
         self,
         message: Message2,
         secrets: Vec<ChainScalar>,
         params: &Params,
-    ) -> anyhow::Result<Message3> {
-        let NB = params.NB();
-        if let Some(bad_index) = message.bucket_mapping.iter().find(|map| **map >= NB) {
+        seed: &[u8; 32],
+    ) -> Result<Message3, ProtocolError> {
+        let rng = &mut seeded_rng(seed, params, b"message3", &message.encode());
+        self.receive_message(message, secrets, params, rng)
+    }
+
+    /// Encodes the state so Alice can survive a crash while waiting for `Message2`. The encoding is
+    /// bound to the contract's fingerprint and ends with a checksum.
+    ///
+    /// **It contains Alice's secrets in the clear.** Use [`Alice1::to_encrypted_bytes`] unless the
+    /// storage is already protected.
+    pub fn to_bytes(&self, params: &Params) -> Vec<u8> {
+        let mut writer = Writer::default();
+        write_header(&mut writer, ALICE1_TAG);
+        writer.bytes(&params.fingerprint());
+        writer.list(&self.commits, |writer, commit| commit.write(writer));
+        writer.list(&self.commit_secrets, |writer, (ri, ri_prime, ri_mapped)| {
+            writer.chain_scalar(ri);
+            writer.scalar(ri_prime);
+            writer.gt(ri_mapped);
+        });
+        append_checksum(writer.finish())
+    }
+
+    /// Loads state saved with [`Alice1::to_bytes`]. It fails if the data is corrupted or was saved
+    /// for a different contract.
+    pub fn from_bytes(bytes: &[u8], params: &Params) -> anyhow::Result<Self> {
+        let mut reader = Reader::new(strip_checksum(bytes)?);
+        read_header(&mut reader, ALICE1_TAG)?;
+        if reader.array::<32>()? != params.fingerprint() {
+            return Err(anyhow!("the state was saved for a different contract"));
+        }
+        let commits = reader.list(Commit::read)?;
+        let commit_secrets = reader.list(|reader| {
+            let ri = reader
+                .chain_scalar()?
+                .mark::<(Secret, NonZero)>()
+                .ok_or_else(|| anyhow!("commitment secret was zero"))?;
+            Ok((ri, reader.scalar()?, reader.gt()?))
+        })?;
+        reader.finish()?;
+        if commits.len() != params.M() || commit_secrets.len() != params.M() {
             return Err(anyhow!(
-                "bucket was mapped to {} which is outside of range 0..{}",
-                bad_index,
-                NB
+                "expected {} commitments but the state has {} commitments and {} secrets",
+                params.M(),
+                commits.len(),
+                commit_secrets.len()
             ));
         }
+        Ok(Alice1 {
+            commit_secrets,
+            commits,
+        })
+    }
+
+    /// Like [`Alice1::to_bytes`] but encrypted with XChaCha20-Poly1305 under `key`.
+    pub fn to_encrypted_bytes(
+        &self,
+        params: &Params,
+        key: &[u8; 32],
+        rng: &mut (impl RngCore + CryptoRng),
+    ) -> Vec<u8> {
+        let mut nonce = XNonce::default();
+        rng.fill_bytes(&mut nonce);
+        let mut writer = Writer::default();
+        write_header(&mut writer, ENCRYPTED_ALICE1_TAG);
+        writer.bytes(&nonce);
+        let plaintext = self.to_bytes(params);
+        let ciphertext = XChaCha20Poly1305::new(key.into())
+            .encrypt(
+                &nonce,
+                Payload {
+                    msg: &plaintext,
+                    aad: &params.fingerprint(),
+                },
+            )
+            .expect("encryption can't fail");
+        writer.bytes(&ciphertext);
+        writer.finish()
+    }
+
+    /// Loads state saved with [`Alice1::to_encrypted_bytes`].
+    pub fn from_encrypted_bytes(
+        bytes: &[u8],
+        params: &Params,
+        key: &[u8; 32],
+    ) -> anyhow::Result<Self> {
+        let mut reader = Reader::new(bytes);
+        read_header(&mut reader, ENCRYPTED_ALICE1_TAG)?;
+        let nonce = XNonce::from(reader.array::<24>()?);
+        let plaintext = XChaCha20Poly1305::new(key.into())
+            .decrypt(
+                &nonce,
+                Payload {
+                    msg: reader.rest(),
+                    aad: &params.fingerprint(),
+                },
+            )
+            .map_err(|_| anyhow!("couldn't decrypt the state -- wrong key or contract"))?;
+        Self::from_bytes(&plaintext, params)
+    }
+
+    /// Produces everything Bob needs in one message by deriving `Message2` from `Message1` with
+    /// [`Bob1::gen_message2_non_interactive`] instead of waiting for Bob to send it.
+    ///
+    /// [`Bob1::gen_message2_non_interactive`]: crate::bob::Bob1::gen_message2_non_interactive
+    pub fn non_interactive_message(
+        self,
+        secrets: Vec<ChainScalar>,
+        params: &Params,
+        rng: &mut (impl RngCore + CryptoRng),
+    ) -> Result<NonInteractiveMessage, ProtocolError> {
+        let message1 = Message1 {
+            commits: self.commits.clone(),
+        };
+        let message2 = crate::bob::Bob1::gen_message2_non_interactive(&message1, params);
+        let message3 = self.receive_message(message2, secrets, params, rng)?;
+        Ok(NonInteractiveMessage { message1, message3 })
+    }
+
+    pub fn receive_message(
+        self,
+        message: Message2,
+        secrets: Vec<ChainScalar>,
+        params: &Params,
+        rng: &mut (impl RngCore + CryptoRng),
+    ) -> Result<Message3, ProtocolError> {
+        let NB = params.NB();
+        if message.bucket_mapping.len() != NB {
+            return Err(ProtocolError::WrongLength {
+                list: ListKind::BucketMapping,
+                expected: NB,
+                got: message.bucket_mapping.len(),
+            });
+        }
+        if let Some((bucket_index, mapped_to)) = message
+            .bucket_mapping
+            .iter()
+            .enumerate()
+            .find(|(_, map)| **map >= NB)
+        {
+            return Err(ProtocolError::BucketMappingOutOfRange {
+                bucket_index,
+                mapped_to: *mapped_to,
+                n_closed: NB,
+            });
+        }
 
         if message.openings.len() != params.num_openings() {
-            return Err(anyhow!(
-                "wrong number of openings requested. Expected {} got {}",
-                params.num_openings(),
-                message.openings.len()
-            ));
+            return Err(ProtocolError::WrongLength {
+                list: ListKind::Openings,
+                expected: params.num_openings(),
+                got: message.openings.len(),
+            });
+        }
+
+        if let Some(bad_opening) = message.openings.iter().find(|i| **i >= params.M()) {
+            return Err(ProtocolError::OpeningOutOfRange {
+                commitment_index: *bad_opening,
+                n_commitments: params.M(),
+            });
+        }
+
+        if secrets.len() != params.n_outcomes as usize {
+            return Err(ProtocolError::WrongLength {
+                list: ListKind::Secrets,
+                expected: params.n_outcomes as usize,
+                got: secrets.len(),
+            });
         }
 
         let Alice1 {
@@ -90,7 +270,7 @@ impl Alice1 {
             let open_it = message.openings.contains(&i);
             i += 1;
             if open_it {
-                openings.push(secret.1.clone());
+                openings.push(secret.1);
             }
             !open_it
         });
@@ -101,19 +281,17 @@ impl Alice1 {
             buckets.push((commits[from], &commit_secrets[from]));
         }
 
-        let proof_system = crate::dleq::ProofSystem::default();
+        let proof_system = params.proof_system();
         let n_oracles = params.oracle_keys.len();
         let anticipated_attestations = (0..n_oracles)
-            .map(|oracle_index| params.iter_anticipations(oracle_index).collect::<Vec<_>>())
+            .map(|oracle_index| params.event_context().anticipations(oracle_index))
             .collect::<Vec<_>>();
 
         let scalar_polys = (0..params.n_outcomes)
             .map(|outcome_index| {
                 let secret = secrets[outcome_index as usize].clone();
-                let mut poly = crate::poly::ScalarPoly::random(
-                    (params.threshold - 1) as usize,
-                    &mut rand::thread_rng(),
-                );
+                let mut poly =
+                    crate::poly::ScalarPoly::random((params.threshold - 1) as usize, rng);
                 poly.push_front(secret);
                 poly
             })
@@ -121,62 +299,70 @@ impl Alice1 {
 
         let bit_map: Vec<Vec<Vec<ChainScalar>>> = (0..n_oracles)
             .map(|_| {
-                (0..params.n_outcome_bits())
+                (0..params.n_outcome_digits())
                     .map(|_| {
-                        (0..params.n_anticipations_per_bit())
-                            .map(|_| ChainScalar::random(&mut rand::thread_rng()))
+                        (0..params.n_anticipations_per_digit())
+                            .map(|_| ChainScalar::random(rng))
                             .collect()
                     })
                     .collect()
             })
             .collect();
 
-        let mut encryptions = vec![];
-
-        for (oracle_index, bits_window) in buckets
-            .chunks(
-                (params.n_outcome_bits()
-                    * params.n_anticipations_per_bit()
-                    * params.bucket_size as u32) as usize,
-            )
-            .enumerate()
-        {
-            for (outcome_bit_index, bit_window) in bits_window
-                .chunks((params.n_anticipations_per_bit() * params.bucket_size as u32) as usize)
+        let n_digits = params.n_outcome_digits() as usize;
+        let n_values = params.n_anticipations_per_digit() as usize;
+        let digit_value_windows = buckets
+            .chunks(params.bucket_size as usize)
+            .collect::<Vec<_>>();
+        let window_rngs = crate::parallel::item_rngs(rng, digit_value_windows.len());
+        let encryptions = crate::parallel::map(
+            digit_value_windows
+                .into_iter()
+                .zip(window_rngs)
                 .enumerate()
-            {
-                for (bit_value_index, bit_value_window) in
-                    bit_window.chunks(params.bucket_size as usize).enumerate()
-                {
-                    let t = &bit_map[oracle_index][outcome_bit_index][bit_value_index];
-                    let anticipated_attestation =
-                        anticipated_attestations[oracle_index][outcome_bit_index][bit_value_index];
-
-                    for (commit, (ri, ri_prime, ri_mapped)) in bit_value_window {
+                .collect(),
+            |(window_index, (digit_value_window, mut rng))| {
+                let oracle_index = window_index / (n_digits * n_values);
+                let outcome_digit_index = (window_index / n_values) % n_digits;
+                let digit_value = window_index % n_values;
+                let t = &bit_map[oracle_index][outcome_digit_index][digit_value];
+                let anticipated_attestation =
+                    anticipated_attestations[oracle_index][outcome_digit_index][digit_value];
+                let anticipation_table =
+                    GtTable::new(anticipated_attestation, digit_value_window.len());
+
+                digit_value_window
+                    .iter()
+                    .map(|(commit, (ri, ri_prime, ri_mapped))| {
                         // compute the ElGamal encryption of ri_mapped
-                        let ri_encryption = anticipated_attestation * ri_prime + ri_mapped;
+                        let ri_encryption = anticipation_table.mul(ri_prime) + ri_mapped;
                         // create proof ElGamal encryption value is same as commitment
                         let proof = crate::dleq::prove_eqaulity(
                             &proof_system,
-                            ri_prime.clone(),
+                            *ri_prime,
                             ri_encryption,
                             anticipated_attestation,
                             params.elgamal_base,
                             commit.C,
+                            params.batch_proofs,
+                            &mut rng,
                         );
 
                         // one-time pad of the secret_share in Z_q
                         let padded_secret = s!(ri + t).mark::<Public>();
-                        encryptions.push((proof, ri_encryption, padded_secret));
-                    }
-                }
-            }
-        }
+                        (proof, ri_encryption, padded_secret)
+                    })
+                    .collect::<Vec<_>>()
+            },
+        )
+        .into_iter()
+        .flatten()
+        .collect();
 
         let secret_share_pads_by_oracle = (0..n_oracles)
             .map(|oracle_index| {
-                let secret_share_pads = compute_pads(&bit_map[oracle_index][..], params.monotone);
-                assert!(secret_share_pads.len() >= params.n_outcomes as usize);
+                let secret_share_pads = compute_pads(&bit_map[oracle_index][..], params.base);
+                debug_assert!(secret_share_pads.len() >= params.n_outcomes as usize);
                 secret_share_pads
                     .into_iter()
                     .enumerate()
@@ -193,14 +379,11 @@ impl Alice1 {
 
         let bit_map_images = bit_map
             .iter()
-            .map(|oracle_bits| {
-                oracle_bits
+            .map(|oracle_digits| {
+                oracle_digits
                     .iter()
-                    .map(|oracle_bits| {
-                        oracle_bits
-                            .iter()
-                            .map(|oracle_bit| g!(oracle_bit * G).normalize())
-                            .collect()
+                    .map(|digit_values| {
+                        digit_values.iter().map(|t| g!(t * G).normalize()).collect()
                     })
                     .collect()
             })
@@ -224,28 +407,107 @@ impl Alice1 {
     }
 }
 
-fn compute_pads(pads: &[Vec<ChainScalar>], monotone: bool) -> Vec<ChainScalar<Secret, Zero>> {
-    _compute_pads(pads.len() - 1, ChainScalar::zero(), pads, monotone)
+/// The randomness for one phase of a seeded run of the protocol for the contract in `params`.
+fn seeded_rng(seed: &[u8; 32], params: &Params, phase: &[u8], context: &[u8]) -> ChaCha20Rng {
+    let rng_seed = Sha256::default()
+        .chain(b"dlc-venc-pairing/alice-seed")
+        .chain(seed)
+        .chain(params.fingerprint())
+        .chain(phase)
+        .chain(context)
+        .finalize();
+    ChaCha20Rng::from_seed(rng_seed.into())
+}
+
+/// The pad of every outcome index that the digits can write: the sum of the pads of its digit
+/// values. In the monotone mode there is no pad for the highest digit value so it adds nothing.
+fn compute_pads(pads: &[Vec<ChainScalar>], base: u8) -> Vec<ChainScalar<Secret, Zero>> {
+    _compute_pads(pads.len() - 1, ChainScalar::zero(), pads, base)
 }
 
 fn _compute_pads(
-    cur_bit: usize,
+    cur_digit: usize,
     acc: ChainScalar<Secret, Zero>,
     pads: &[Vec<ChainScalar>],
-    monotone: bool,
+    base: u8,
 ) -> Vec<ChainScalar<Secret, Zero>> {
-    let zero = s!(acc + { &pads[cur_bit][0] });
-    let one = if monotone {
-        acc
+    let values = (0..base as usize).map(|digit_value| match pads[cur_digit].get(digit_value) {
+        Some(pad) => s!(acc + pad),
+        None => acc.clone(),
+    });
+
+    if cur_digit == 0 {
+        values.collect()
     } else {
-        s!(acc + { &pads[cur_bit][1] })
+        values
+            .flat_map(|acc| _compute_pads(cur_digit - 1, acc, pads, base))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        bob::test::{test_params, test_setup},
+        oracle::Oracle,
     };
 
-    if cur_bit == 0 {
-        vec![zero, one]
-    } else {
-        let mut children = _compute_pads(cur_bit - 1, zero, pads, monotone);
-        children.extend(_compute_pads(cur_bit - 1, one, pads, monotone));
-        children
+    #[test]
+    fn alice1_persistence() {
+        let rng = &mut rand::thread_rng();
+        let params = test_params(&[Oracle::random(rng)], 1);
+        let alice = test_setup(&params, rng).alice;
+
+        let restored = Alice1::from_bytes(&alice.to_bytes(&params), &params).unwrap();
+        assert_eq!(restored.to_bytes(&params), alice.to_bytes(&params));
+
+        let key = [7u8; 32];
+        let encrypted = alice.to_encrypted_bytes(&params, &key, rng);
+        let restored = Alice1::from_encrypted_bytes(&encrypted, &params, &key).unwrap();
+        assert_eq!(restored.to_bytes(&params), alice.to_bytes(&params));
+        assert!(Alice1::from_encrypted_bytes(&encrypted, &params, &[8u8; 32]).is_err());
+    }
+
+    #[test]
+    fn pads_sum_the_digit_values() {
+        let rng = &mut rand::thread_rng();
+        // two base-3 digits in the monotone mode so the value 2 has no pad
+        let pads = (0..2)
+            .map(|_| (0..2).map(|_| ChainScalar::random(rng)).collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+        let computed = compute_pads(&pads, 3);
+        assert_eq!(computed.len(), 9);
+        for (outcome_index, pad) in computed.iter().enumerate() {
+            let expected = crate::common::to_digits(outcome_index as u32, 3, 2)
+                .into_iter()
+                .enumerate()
+                .filter(|(_, digit_value)| *digit_value < 2)
+                .fold(ChainScalar::zero(), |acc, (digit_index, digit_value)| {
+                    s!(acc + { &pads[digit_index][digit_value as usize] })
+                });
+            assert_eq!(pad, &expected);
+        }
+    }
+
+    #[test]
+    fn seeded_alice_is_deterministic() {
+        let rng = &mut rand::thread_rng();
+        let params = test_params(&[Oracle::random(rng)], 1);
+        let secrets = test_setup(&params, rng).secrets;
+        let seed = [3u8; 32];
+
+        let (alice, message1) = Alice1::new_seeded(&seed, &params);
+        let (crashed_alice, regenerated) = Alice1::new_seeded(&seed, &params);
+        assert_eq!(message1, regenerated);
+
+        let message2 = crate::bob::Bob1::gen_message2(&message1.commits, &params, rng);
+        let message3 = alice
+            .receive_message_seeded(message2.clone(), secrets.clone(), &params, &seed)
+            .unwrap();
+        let regenerated = crashed_alice
+            .receive_message_seeded(message2, secrets, &params, &seed)
+            .unwrap();
+        assert_eq!(message3, regenerated);
     }
 }
@@ -0,0 +1,813 @@
+//! Runs the whole protocol between an in-process Alice, Bob and set of oracles and reports how
+//! long each phase took and how much was sent.
+//!
+//! ```no_run
+//! # use dlc_venc_pairing::bench::{run_benchmark, BenchConfig};
+//! let report = run_benchmark(&BenchConfig::new(100, 3, 2)).unwrap();
+//! println!("{}", report);
+//! ```
+use crate::{
+    alice::Alice1,
+    bob::{Bob1, Bob2},
+    common::{compute_optimal_base, CostWeights, Params, ParamsBuilder},
+    fixed_base::GtTable,
+    messages::*,
+    oracle::Oracle,
+};
+use bls12_381::{G1Affine, Scalar};
+use ff::Field;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha20Rng;
+use secp256k1_zkp::{EcdsaAdaptorSignature, Message, PublicKey, Secp256k1, SecretKey};
+use secp256kfun::{g, marker::*, s, Point, Scalar as ChainScalar, G};
+use serde::{Serialize, Serializer};
+use std::{
+    fmt,
+    time::{Duration, Instant},
+};
+
+/// What to benchmark. These are the same knobs the `run` binary has.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct BenchConfig {
+    /// Bits of statistical security for the cut-and-choose.
+    pub security_param: u8,
+    pub n_outcomes: u32,
+    pub n_oracles: u16,
+    pub threshold: u16,
+    pub monotone: bool,
+    /// Send DLEQ proofs Bob can check all at once (see [`ParamsBuilder::batch_proofs`]).
+    pub batch_proofs: bool,
+    /// The base the oracles announce. `None` has them announce the one
+    /// [`compute_optimal_base`] suggests.
+    pub base: Option<u8>,
+    /// Time signing and verifying an ECDSA adaptor signature for each outcome as well, to compare
+    /// against DLCs built on them.
+    pub model_ecdsa_adaptor: bool,
+    pub non_interactive: bool,
+    /// How many bits of grinding to allow for in non-interactive mode.
+    pub grinding_bits: u8,
+    /// Seed all randomness (oracle keys, secrets and the protocol) so a run can be reproduced.
+    pub seed: Option<u64>,
+    /// How many threads to use with the `parallel` feature (defaults to one per core).
+    pub threads: Option<usize>,
+    /// The weights to choose the cut-and-choose parameters with (see [`calibrate`]). `None` uses
+    /// the defaults.
+    pub cost_weights: Option<CostWeights>,
+}
+
+impl BenchConfig {
+    /// A config with the same defaults as the `run` binary.
+    pub fn new(n_outcomes: u32, n_oracles: u16, threshold: u16) -> Self {
+        BenchConfig {
+            security_param: 30,
+            n_outcomes,
+            n_oracles,
+            threshold,
+            monotone: false,
+            batch_proofs: false,
+            base: None,
+            model_ecdsa_adaptor: false,
+            non_interactive: false,
+            grinding_bits: 40,
+            seed: None,
+            threads: None,
+            cost_weights: None,
+        }
+    }
+}
+
+/// Everything measured during a [`run_benchmark`].
+#[derive(Clone, Debug, Serialize)]
+pub struct BenchReport {
+    pub config: BenchConfig,
+    pub params: ParamsReport,
+    pub parallel: bool,
+    pub threads: usize,
+    /// In the order they happened.
+    pub phases: Vec<PhaseReport>,
+    /// The encoded size of each message sent.
+    pub messages: Vec<MessageReport>,
+    pub operations: OperationCounts,
+    #[serde(serialize_with = "serialize_seconds")]
+    pub total: Duration,
+    /// The total less the phases that can happen before Bob is online.
+    #[serde(serialize_with = "serialize_seconds")]
+    pub sans_preprocessing: Duration,
+    pub outcome_index: u32,
+    /// How many oracles' shares Bob had to reject (always zero with honest oracles).
+    pub rejected_shares: usize,
+}
+
+/// The parameters [`ParamsBuilder`] chose for the config.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct ParamsReport {
+    pub closed_proportion: f64,
+    pub bucket_size: u8,
+    pub base: u8,
+    pub n_outcome_digits: u32,
+    pub n_commitments: usize,
+    pub n_openings: usize,
+    pub n_encryptions: usize,
+    /// The soundness the cut-and-choose actually gives (see [`Params::soundness_bits`]).
+    pub soundness_bits: f64,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct PhaseReport {
+    pub name: &'static str,
+    pub party: Party,
+    #[serde(serialize_with = "serialize_seconds")]
+    pub elapsed: Duration,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Party {
+    Alice,
+    Bob,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct MessageReport {
+    pub name: &'static str,
+    pub bytes: usize,
+}
+
+/// How many of each of the expensive operations the run did.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct OperationCounts {
+    /// ElGamal commitments Alice made (and Bob received).
+    pub commitments: usize,
+    /// Commitments opened for Bob to check.
+    pub openings: usize,
+    /// Encryptions of the anticipated attestations, each with a DLEQ proof.
+    pub encryptions: usize,
+    /// Anticipated attestations computed (one pairing each).
+    pub anticipations: usize,
+    /// Padded secret shares Bob checked, one per oracle and outcome.
+    pub secret_share_pads: usize,
+    /// Digit signatures Bob verified when the oracles attested.
+    pub attestation_signatures: usize,
+    pub ecdsa_adaptor_signatures: usize,
+}
+
+/// Runs the protocol with `config` end to end, including the oracles attesting to a random
+/// outcome and Bob recovering its secret.
+pub fn run_benchmark(config: &BenchConfig) -> anyhow::Result<BenchReport> {
+    with_threads(config.threads, || run(config))
+}
+
+#[cfg(feature = "parallel")]
+fn with_threads<T>(
+    threads: Option<usize>,
+    f: impl FnOnce() -> anyhow::Result<T> + Send,
+) -> anyhow::Result<T>
+where
+    T: Send,
+{
+    match threads {
+        Some(threads) => rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build()?
+            .install(f),
+        None => f(),
+    }
+}
+
+#[cfg(not(feature = "parallel"))]
+fn with_threads<T>(
+    threads: Option<usize>,
+    f: impl FnOnce() -> anyhow::Result<T> + Send,
+) -> anyhow::Result<T>
+where
+    T: Send,
+{
+    if threads.is_some_and(|threads| threads != 1) {
+        anyhow::bail!("using more than one thread needs the parallel feature");
+    }
+    f()
+}
+
+fn run(config: &BenchConfig) -> anyhow::Result<BenchReport> {
+    let rng = &mut match config.seed {
+        Some(seed) => ChaCha20Rng::seed_from_u64(seed),
+        None => ChaCha20Rng::from_entropy(),
+    };
+
+    let oracles = (0..config.n_oracles)
+        .map(|_| Oracle::random(rng))
+        .collect::<Vec<_>>();
+    let cost_weights = config.cost_weights.unwrap_or_default();
+    // the oracles announce the base the cost model suggests for this contract
+    let base = match config.base {
+        Some(base) => base,
+        None => compute_optimal_base(
+            if config.non_interactive {
+                config.security_param.saturating_add(config.grinding_bits)
+            } else {
+                config.security_param
+            },
+            config.n_outcomes,
+            config.n_oracles as u32,
+            config.monotone,
+            &cost_weights,
+        )?,
+    };
+    let announcements = oracles
+        .iter()
+        .map(|oracle| oracle.announce("test", config.n_outcomes, base, 0))
+        .collect::<Vec<_>>();
+
+    let mut builder = ParamsBuilder::from_announcements(&announcements, config.threshold)?
+        .monotone(config.monotone)
+        .batch_proofs(config.batch_proofs)
+        .security_param(config.security_param)
+        .cost_weights(cost_weights);
+    if config.non_interactive {
+        builder = builder.non_interactive(config.grinding_bits);
+    }
+    let params = builder.build()?;
+
+    let secrets = (0..params.n_outcomes())
+        .map(|_| ChainScalar::random(rng))
+        .collect::<Vec<_>>();
+    let secret_images = secrets
+        .iter()
+        .map(|secret| g!(secret * G).normalize())
+        .collect::<Vec<_>>();
+
+    let mut recorder = Recorder::default();
+    let start = Instant::now();
+    let adaptor_sigs = if config.model_ecdsa_adaptor {
+        recorder.time("ecdsa_adaptor_sign", Party::Alice, || {
+            sign_adaptor_sigs(&secret_images)
+        })
+    } else {
+        vec![]
+    };
+
+    let (bob, start_online) = if config.non_interactive {
+        let message_bytes = recorder.time("gen_non_interactive_message", Party::Alice, || {
+            let alice = Alice1::new(&params, rng).0;
+            alice
+                .non_interactive_message(secrets, &params, rng)
+                .map(|message| message.encode())
+        })?;
+        recorder.message("non_interactive_message", &message_bytes);
+        let start_online = Instant::now();
+        verify_adaptor_sigs(&mut recorder, adaptor_sigs);
+        let bob = recorder.time("process_non_interactive_message", Party::Bob, || {
+            Bob1::receive_non_interactive(
+                NonInteractiveMessage::decode(&message_bytes)?,
+                secret_images,
+                &params,
+            )
+            .map_err(anyhow::Error::from)
+        })?;
+        (bob, start_online)
+    } else {
+        let (alice, m1_bytes) = recorder.time("gen_message1", Party::Alice, || {
+            let (alice, m1) = Alice1::new(&params, rng);
+            (alice, m1.encode())
+        });
+        recorder.message("message1", &m1_bytes);
+
+        let start_online = Instant::now();
+        let (bob, m2_bytes) = recorder.time("gen_message2", Party::Bob, || {
+            let (bob, m2) = Bob1::new(Message1::decode(&m1_bytes)?, &params, rng)?;
+            anyhow::Ok((bob, m2.encode()))
+        })?;
+        // we imagine that verifying ECDSA adaptor signatures happens while generating message 2
+        verify_adaptor_sigs(&mut recorder, adaptor_sigs);
+        recorder.message("message2", &m2_bytes);
+
+        let m3_bytes = recorder.time("gen_message3", Party::Alice, || {
+            let m3 = alice.receive_message(Message2::decode(&m2_bytes)?, secrets, &params, rng)?;
+            anyhow::Ok(m3.encode())
+        })?;
+        recorder.message("message3", &m3_bytes);
+
+        let bob = recorder.time("process_message3", Party::Bob, || {
+            bob.receive_message(Message3::decode(&m3_bytes)?, secret_images, &params)
+                .map_err(anyhow::Error::from)
+        })?;
+        (bob, start_online)
+    };
+    let total = start.elapsed();
+    let sans_preprocessing = start_online.elapsed();
+
+    let outcome_index = rng.gen_range(0..params.n_outcomes());
+    let rejected_shares = recorder.time("decrypt_outcome", Party::Bob, || {
+        decrypt_outcome(&bob, &oracles, outcome_index, &params)
+    })?;
+
+    Ok(BenchReport {
+        config: config.clone(),
+        params: ParamsReport::new(&params),
+        parallel: cfg!(feature = "parallel"),
+        threads: crate::parallel::n_threads(),
+        phases: recorder.phases,
+        messages: recorder.messages,
+        operations: OperationCounts::new(&params, config),
+        total,
+        sans_preprocessing,
+        outcome_index,
+        rejected_shares,
+    })
+}
+
+#[derive(Default)]
+struct Recorder {
+    phases: Vec<PhaseReport>,
+    messages: Vec<MessageReport>,
+}
+
+impl Recorder {
+    fn time<T>(&mut self, name: &'static str, party: Party, f: impl FnOnce() -> T) -> T {
+        let start = Instant::now();
+        let output = f();
+        self.phases.push(PhaseReport {
+            name,
+            party,
+            elapsed: start.elapsed(),
+        });
+        output
+    }
+
+    fn message(&mut self, name: &'static str, bytes: &[u8]) {
+        self.messages.push(MessageReport {
+            name,
+            bytes: bytes.len(),
+        });
+    }
+}
+
+impl ParamsReport {
+    fn new(params: &Params) -> Self {
+        ParamsReport {
+            closed_proportion: params.closed_proportion(),
+            bucket_size: params.bucket_size(),
+            base: params.base(),
+            n_outcome_digits: params.n_outcome_digits(),
+            n_commitments: params.M(),
+            n_openings: params.num_openings(),
+            n_encryptions: params.NB(),
+            soundness_bits: params.soundness_bits(),
+        }
+    }
+}
+
+impl OperationCounts {
+    fn new(params: &Params, config: &BenchConfig) -> Self {
+        let n_oracles = params.oracle_keys().len();
+        OperationCounts {
+            commitments: params.M(),
+            openings: params.num_openings(),
+            encryptions: params.NB(),
+            anticipations: n_oracles * params.n_anticipations_per_oracle() as usize,
+            secret_share_pads: n_oracles * params.n_outcomes() as usize,
+            attestation_signatures: n_oracles * params.n_outcome_digits() as usize,
+            ecdsa_adaptor_signatures: if config.model_ecdsa_adaptor {
+                params.n_outcomes() as usize
+            } else {
+                0
+            },
+        }
+    }
+}
+
+impl BenchReport {
+    pub fn transmitted(&self) -> usize {
+        self.messages.iter().map(|message| message.bytes).sum()
+    }
+}
+
+impl fmt::Display for BenchReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let config = &self.config;
+        writeln!(
+            f,
+            "parallel: {} threads: {}",
+            if self.parallel { "on" } else { "off" },
+            self.threads
+        )?;
+        writeln!(
+            f,
+            "Params s: {} n_oracles: {} n_outcomes: {} threshold: {} base: {} n_encryptions: {} bucket_size: {} proportion_closed: {} soundness_bits: {:.2}",
+            config.security_param,
+            config.n_oracles,
+            config.n_outcomes,
+            config.threshold,
+            self.params.base,
+            self.params.n_commitments,
+            self.params.bucket_size,
+            self.params.closed_proportion,
+            self.params.soundness_bits
+        )?;
+        for phase in &self.phases {
+            let party = match phase.party {
+                Party::Alice => "alice",
+                Party::Bob => "bob",
+            };
+            writeln!(f, "{} elapsed: {:?} ({})", phase.name, phase.elapsed, party)?;
+        }
+        for message in &self.messages {
+            writeln!(f, "{} transmitted: {}", message.name, message.bytes)?;
+        }
+        write!(
+            f,
+            "Total elapsed: {:?} sans-preprocessing: {:?} transmitted: {}",
+            self.total,
+            self.sans_preprocessing,
+            self.transmitted()
+        )
+    }
+}
+
+/// Measures [`CostWeights`] on this machine by doing each part of the cut-and-choose for
+/// `n_samples` commitments the way Alice and Bob do it in the protocol (including encoding and
+/// decoding what they send) and dividing the time taken by `n_samples`.
+///
+/// Opening checks are batched so their cost per commitment shrinks as there are more of them. Use
+/// roughly as many samples as the contracts you care about open. `batch_proofs` and `threads` are
+/// the same as [`BenchConfig::batch_proofs`] and [`BenchConfig::threads`].
+pub fn calibrate(
+    n_samples: usize,
+    batch_proofs: bool,
+    threads: Option<usize>,
+) -> anyhow::Result<CostWeights> {
+    with_threads(threads, || time_cost_weights(n_samples, batch_proofs))
+}
+
+fn time_cost_weights(n_samples: usize, batch_proofs: bool) -> anyhow::Result<CostWeights> {
+    if n_samples == 0 {
+        anyhow::bail!("calibration needs at least one sample");
+    }
+    let rng = &mut rand::thread_rng();
+    let oracle = Oracle::random(rng);
+    let params = ParamsBuilder::new(vec![oracle.key()], "calibrate", 2, 1)
+        .batch_proofs(batch_proofs)
+        .cut_and_choose(0.5, 1)
+        .build()?;
+    let anticipation = params.anticipate_at_index(0, 0, 0);
+    let proof_system = params.proof_system();
+    // build the cached tables up front since the protocol spreads their cost over every commitment
+    params.elgamal_base_table();
+    params.event_context();
+    let per_sample = |elapsed: Duration| elapsed.as_secs_f64() * 1000.0 / n_samples as f64;
+
+    let start = Instant::now();
+    let (commits, secrets): (Vec<_>, Vec<_>) =
+        crate::parallel::map(crate::parallel::item_rngs(rng, n_samples), |mut rng| {
+            let ri = ChainScalar::random(&mut rng);
+            let (ri_mapped, pad) = params.map_Zq_to_Gt(&ri, &mut rng);
+            let ri_prime = Scalar::random(&mut rng);
+            let commit = Commit {
+                C: (
+                    (G1Affine::generator() * ri_prime).into(),
+                    params.elgamal_base_table().mul(&ri_prime) + ri_mapped,
+                ),
+                R: g!(ri * G).normalize(),
+                pad,
+            };
+            (commit, (ri, ri_prime, ri_mapped))
+        })
+        .into_iter()
+        .unzip();
+    let commits = Message1::decode(&Message1 { commits }.encode())?.commits;
+    let any = per_sample(start.elapsed());
+
+    let start = Instant::now();
+    let opened = commits.iter().cloned().enumerate().collect::<Vec<_>>();
+    let openings = Message3::decode(
+        &Message3 {
+            encryptions: vec![],
+            polys: vec![],
+            openings: secrets.iter().map(|(_, ri_prime, _)| *ri_prime).collect(),
+            bit_map_images: vec![],
+            secret_share_pads_by_oracle: vec![],
+        }
+        .encode(),
+    )?
+    .openings;
+    crate::bob::check_openings(&opened, &openings, &params)?;
+    let open = per_sample(start.elapsed());
+
+    let start = Instant::now();
+    let t = ChainScalar::random(rng);
+    let T = g!(t * G).normalize();
+    let anticipation_table = GtTable::new(anticipation, n_samples);
+    let encryptions = crate::parallel::map(
+        commits
+            .iter()
+            .zip(&secrets)
+            .zip(crate::parallel::item_rngs(rng, n_samples))
+            .collect(),
+        |((commit, (ri, ri_prime, ri_mapped)), mut rng)| {
+            let ri_encryption = anticipation_table.mul(ri_prime) + ri_mapped;
+            let proof = crate::dleq::prove_eqaulity(
+                &proof_system,
+                *ri_prime,
+                ri_encryption,
+                anticipation,
+                params.elgamal_base(),
+                commit.C,
+                batch_proofs,
+                &mut rng,
+            );
+            (proof, ri_encryption, s!(ri + t).mark::<Public>())
+        },
+    );
+    let encryptions = Message3::decode(
+        &Message3 {
+            encryptions,
+            polys: vec![],
+            openings: vec![],
+            bit_map_images: vec![],
+            secret_share_pads_by_oracle: vec![],
+        }
+        .encode(),
+    )?
+    .encryptions;
+    let items = encryptions
+        .iter()
+        .zip(&commits)
+        .map(|((proof, ri_encryption, _), commit)| (proof, *ri_encryption, anticipation, commit.C))
+        .collect::<Vec<_>>();
+    let proofs_valid = if batch_proofs {
+        crate::dleq::verify_eqaulity_batch(&proof_system, params.elgamal_base(), &items)
+    } else {
+        crate::parallel::map(items, |(proof, ri_encryption, anticipation, commit)| {
+            crate::dleq::verify_eqaulity(
+                &proof_system,
+                proof,
+                ri_encryption,
+                anticipation,
+                params.elgamal_base(),
+                commit,
+            )
+        })
+        .into_iter()
+        .all(|valid| valid)
+    };
+    let pads_valid = encryptions
+        .iter()
+        .zip(&commits)
+        .all(|((_, _, padded_T), commit)| g!(T + commit.R) == g!(padded_T * G));
+    let closed = per_sample(start.elapsed());
+    if !(proofs_valid && pads_valid) {
+        anyhow::bail!("calibration produced invalid proofs");
+    }
+
+    let weights = CostWeights { any, open, closed };
+    weights.validate()?;
+    Ok(weights)
+}
+
+/// The median and spread of one quantity over repeated runs of the same config.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Metric {
+    pub name: String,
+    pub unit: Unit,
+    pub median: f64,
+    pub min: f64,
+    pub max: f64,
+    /// The median absolute deviation from the median.
+    pub mad: f64,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Unit {
+    Seconds,
+    Bytes,
+}
+
+/// Repeated runs of one config summarized by [`summarize`].
+#[derive(Clone, Debug, Serialize)]
+pub struct BenchSummary {
+    pub config: BenchConfig,
+    pub params: ParamsReport,
+    pub repetitions: usize,
+    /// Each phase, the totals, each message and the total transmitted.
+    pub metrics: Vec<Metric>,
+}
+
+/// Summarizes reports from running the same config several times. Returns `None` if `reports` is
+/// empty.
+pub fn summarize(reports: &[BenchReport]) -> Option<BenchSummary> {
+    let first = reports.first()?;
+    let mut metrics = vec![];
+    for phase in &first.phases {
+        metrics.push(Metric::new(
+            phase.name,
+            Unit::Seconds,
+            reports.iter().map(|report| {
+                report
+                    .phases
+                    .iter()
+                    .find(|other| other.name == phase.name)
+                    .map_or(0.0, |other| other.elapsed.as_secs_f64())
+            }),
+        ));
+    }
+    metrics.push(Metric::new(
+        "total",
+        Unit::Seconds,
+        reports.iter().map(|report| report.total.as_secs_f64()),
+    ));
+    metrics.push(Metric::new(
+        "sans_preprocessing",
+        Unit::Seconds,
+        reports
+            .iter()
+            .map(|report| report.sans_preprocessing.as_secs_f64()),
+    ));
+    for message in &first.messages {
+        metrics.push(Metric::new(
+            message.name,
+            Unit::Bytes,
+            reports.iter().map(|report| {
+                report
+                    .messages
+                    .iter()
+                    .find(|other| other.name == message.name)
+                    .map_or(0.0, |other| other.bytes as f64)
+            }),
+        ));
+    }
+    metrics.push(Metric::new(
+        "transmitted",
+        Unit::Bytes,
+        reports.iter().map(|report| report.transmitted() as f64),
+    ));
+
+    Some(BenchSummary {
+        config: first.config.clone(),
+        params: first.params.clone(),
+        repetitions: reports.len(),
+        metrics,
+    })
+}
+
+impl Metric {
+    fn new(name: &str, unit: Unit, samples: impl Iterator<Item = f64>) -> Self {
+        let samples = samples.collect::<Vec<_>>();
+        let median = median(samples.clone());
+        Metric {
+            name: name.to_string(),
+            unit,
+            median,
+            min: samples.iter().cloned().fold(f64::INFINITY, f64::min),
+            max: samples.iter().cloned().fold(f64::NEG_INFINITY, f64::max),
+            mad: self::median(
+                samples
+                    .iter()
+                    .map(|sample| (sample - median).abs())
+                    .collect(),
+            ),
+        }
+    }
+}
+
+fn median(mut samples: Vec<f64>) -> f64 {
+    samples.sort_by(f64::total_cmp);
+    let mid = samples.len() / 2;
+    if samples.len().is_multiple_of(2) {
+        (samples[mid - 1] + samples[mid]) / 2.0
+    } else {
+        samples[mid]
+    }
+}
+
+fn serialize_seconds<S: Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_f64(duration.as_secs_f64())
+}
+
+// imagine this is the secret key Alice is using for bitcoin transactions
+const ALICE_ECDSA_SECRET_KEY: [u8; 32] = [42u8; 32];
+// and this is a bitcoin transaction
+const ARBITRARY_MESSAGE: [u8; 32] = [42u8; 32];
+
+fn sign_adaptor_sigs(secret_images: &[Point]) -> Vec<(EcdsaAdaptorSignature, PublicKey)> {
+    let secp = Secp256k1::new();
+    let secret_key = SecretKey::from_slice(&ALICE_ECDSA_SECRET_KEY).unwrap();
+    let message = Message::from_slice(&ARBITRARY_MESSAGE).unwrap();
+    secret_images
+        .iter()
+        .map(|image| {
+            let encryption_key = PublicKey::from_slice(image.to_bytes().as_slice()).unwrap();
+            (
+                EcdsaAdaptorSignature::encrypt_no_aux_rand(
+                    &secp,
+                    &message,
+                    &secret_key,
+                    &encryption_key,
+                ),
+                encryption_key,
+            )
+        })
+        .collect()
+}
+
+fn verify_adaptor_sigs(
+    recorder: &mut Recorder,
+    adaptor_sigs: Vec<(EcdsaAdaptorSignature, PublicKey)>,
+) {
+    if adaptor_sigs.is_empty() {
+        return;
+    }
+    recorder.time("ecdsa_adaptor_verify", Party::Bob, || {
+        let secp = Secp256k1::new();
+        let secret_key = SecretKey::from_slice(&ALICE_ECDSA_SECRET_KEY).unwrap();
+        let public_key = PublicKey::from_secret_key(&secp, &secret_key);
+        let message = Message::from_slice(&ARBITRARY_MESSAGE).unwrap();
+        for (adaptor_sig, encryption_key) in adaptor_sigs {
+            assert!(adaptor_sig
+                .verify(&secp, &message, &public_key, &encryption_key)
+                .is_ok());
+        }
+    })
+}
+
+/// Has every oracle attest to `outcome_index` and returns how many shares Bob rejected.
+fn decrypt_outcome(
+    bob: &Bob2,
+    oracles: &[Oracle],
+    outcome_index: u32,
+    params: &Params,
+) -> anyhow::Result<usize> {
+    let attestations = oracles
+        .iter()
+        .map(|oracle| {
+            oracle.attest(
+                params.event_id(),
+                params.base(),
+                params.n_outcome_digits() as usize,
+                outcome_index,
+            )
+        })
+        .collect::<Vec<_>>();
+    let recovered = bob.receive_oracle_attestation(outcome_index, &attestations, params)?;
+    Ok(recovered.rejected.len())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn report_covers_the_run() {
+        let config = BenchConfig {
+            security_param: 10,
+            seed: Some(42),
+            ..BenchConfig::new(4, 2, 1)
+        };
+        let report = run_benchmark(&config).unwrap();
+        assert_eq!(
+            report
+                .phases
+                .iter()
+                .map(|phase| phase.name)
+                .collect::<Vec<_>>(),
+            [
+                "gen_message1",
+                "gen_message2",
+                "gen_message3",
+                "process_message3",
+                "decrypt_outcome"
+            ]
+        );
+        assert_eq!(report.messages.len(), 3);
+        assert_eq!(report.params.n_commitments, report.operations.commitments);
+        assert_eq!(report.rejected_shares, 0);
+        let json = serde_json::to_value(&report).unwrap();
+        assert_eq!(json["config"]["n_outcomes"], 4);
+        assert_eq!(json["total"].as_f64(), Some(report.total.as_secs_f64()));
+
+        assert!(summarize(&[]).is_none());
+        let summary = summarize(&[report.clone(), report.clone()]).unwrap();
+        assert_eq!(summary.repetitions, 2);
+        let total = summary
+            .metrics
+            .iter()
+            .find(|metric| metric.name == "total")
+            .unwrap();
+        assert_eq!(total.median, report.total.as_secs_f64());
+        assert_eq!(total.mad, 0.0);
+        let transmitted = summary.metrics.last().unwrap();
+        assert_eq!(transmitted.unit, Unit::Bytes);
+        assert_eq!(transmitted.max, report.transmitted() as f64);
+    }
+
+    #[test]
+    fn calibration_measures_every_weight() {
+        for batch_proofs in [false, true] {
+            let weights = calibrate(4, batch_proofs, None).unwrap();
+            for weight in [weights.any, weights.open, weights.closed] {
+                assert!(weight.is_finite() && weight > 0.0);
+            }
+        }
+        assert!(calibrate(0, false, None).is_err());
+    }
+}
@@ -1,38 +1,29 @@
-use bls12_381::{
-    hash_to_curve::{ExpandMsgXmd, HashToCurve},
-    G1Projective, G2Affine,
-};
-use bls12_381::{pairing as e, G2Prepared};
-use clap::Parser;
-use dlc_venc_pairing::messages::EstimateSize;
+use clap::{Args, Parser, Subcommand, ValueEnum};
 use dlc_venc_pairing::{
-    alice::*,
-    bob::*,
-    common::{compute_optimal_params, Params},
-    oracle::Oracle,
+    bench::{calibrate, run_benchmark, summarize, BenchConfig, BenchSummary, Unit},
+    common::CostWeights,
 };
-use rand::Rng;
-use secp256k1_zkp::{EcdsaAdaptorSignature, Secp256k1};
-use secp256k1_zkp::{Message, PublicKey, SecretKey};
-use secp256kfun::{g, Scalar as ChainScalar, G};
-use sha2::Sha256;
-use std::time::Instant;
+use std::{fs::File, io::Write, path::PathBuf, str::FromStr};
 
 #[derive(Parser, Debug)]
 #[clap(author, version, about, long_about = None)]
+#[clap(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
 struct CliArgs {
+    #[clap(subcommand)]
+    command: Option<Command>,
+
     /// The security parameter (how many bits of security for the overall protocol)
     #[clap(short, default_value_t = 30)]
     s: u8,
     /// The number of outcomes
-    #[clap(long)]
-    n_outcomes: u32,
+    #[clap(long, required = true)]
+    n_outcomes: Option<u32>,
     /// The number of oracles
-    #[clap(long)]
-    n_oracles: u16,
+    #[clap(long, required = true)]
+    n_oracles: Option<u16>,
     /// The threshold of oracles that is required to attest
-    #[clap(long)]
-    threshold: u16,
+    #[clap(long, required = true)]
+    threshold: Option<u16>,
     /// use the payout monotoniciity optimization.
     ///
     /// This assumes that the access strucuture can allow the Bob to access all secrets assigned to
@@ -41,154 +32,351 @@ struct CliArgs {
     #[clap(long)]
     monotone: bool,
 
+    /// Have Alice send DLEQ proofs that Bob can check all at once. They are faster to check but
+    /// each one is 305 bytes bigger.
+    #[clap(long)]
+    batch_proofs: bool,
+
+    /// The base the oracles announce they'll write the outcome in and attest to digit by digit
+    /// (the one the cost model suggests if not given)
+    #[clap(long)]
+    base: Option<u8>,
+
     /// Model ECDSA adaptor signature generation. This is so we can have applies-to-apples
     /// comparision against rust-dlc which at the time of writing uses ECDSA adaptor signatures.
     /// The total elapsed will include the time needed to generate the ECDSA adaptor signatures.
     #[clap(long)]
     model_ecdsa_adaptor: bool,
+
+    /// Run the non-interactive variant where Alice derives Bob's challenge by hashing her
+    /// commitments and sends everything in one message.
+    #[clap(long)]
+    non_interactive: bool,
+
+    /// In non-interactive mode, the log2 of how many attempts we assume Alice can make at
+    /// grinding a favourable challenge. The cut-and-choose parameters are increased to compensate.
+    #[clap(long, default_value_t = 40)]
+    grinding_bits: u8,
+
+    /// Seed all randomness (oracle keys, secrets and the protocol itself) so a run can be
+    /// reproduced exactly.
+    #[clap(long)]
+    seed: Option<u64>,
+
+    /// The number of threads to use when built with the `parallel` feature (defaults to one per
+    /// core).
+    #[clap(long)]
+    threads: Option<usize>,
+
+    /// A JSON file of cost weights from the `calibrate` subcommand to choose the cut-and-choose
+    /// parameters with
+    #[clap(long)]
+    cost_weights: Option<PathBuf>,
+
+    /// How to print the report.
+    #[clap(long, value_enum, default_value_t = Format::Text)]
+    format: Format,
 }
 
-fn main() -> anyhow::Result<()> {
-    let args = CliArgs::parse();
-    let elgamal_base =
-        <G1Projective as HashToCurve<ExpandMsgXmd<Sha256>>>::hash_to_curve(b"dlc".as_ref(), b"dlc");
-
-    let elgamal_base = e(&elgamal_base.into(), &G2Affine::generator());
-
-    let oracles = (0..args.n_oracles)
-        .map(|_| Oracle::random(&mut rand::thread_rng()))
-        .collect::<Vec<_>>();
-
-    let (closed_proportion, bucket_size) = compute_optimal_params(
-        args.s,
-        args.n_outcomes as u32,
-        args.n_oracles as u32,
-        args.monotone,
-    );
-
-    let params = Params {
-        oracle_keys: oracles.iter().map(|oracle| oracle.public_key()).collect(),
-        n_outcomes: args.n_outcomes,
-        bucket_size,
-        closed_proportion,
-        elgamal_base,
-        threshold: args.threshold,
-        event_id: "test".to_string(),
-        g2_prepared: G2Prepared::from(G2Affine::generator()),
-        monotone: args.monotone,
-    };
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+enum Format {
+    Text,
+    Json,
+}
 
-    println!("Params s: {} n_oracles: {} n_outcomes: {} threshold: {} n_encryptions: {} bucket_size: {} proportion_closed: {}",
-             args.s, args.n_oracles, args.n_outcomes, args.threshold, params.M(), params.bucket_size, params.closed_proportion);
-
-    let secrets = (0..params.n_outcomes)
-        .map(|_| ChainScalar::random(&mut rand::thread_rng()))
-        .collect::<Vec<_>>();
-
-    let secret_images: Vec<_> = secrets.iter().map(|s| g!(s * G).normalize()).collect();
-    let secp = Secp256k1::new();
-    let arbirary_message = Message::from_slice([42u8; 32].as_ref()).unwrap();
-
-    let alice_ecdsa_secret_key = SecretKey::from_slice([42u8; 32].as_ref()).unwrap();
-
-    let start_gen_msg_1 = Instant::now();
-    let adaptor_sigs = if args.model_ecdsa_adaptor {
-        // imagine this is the secret key she's using for bitcoin transactions.
-        // we could imagine this is a bitcoin transaction
-        let ecdsa_adaptor_sigs = secret_images
-            .iter()
-            .map(|image| {
-                let encryption_key = PublicKey::from_slice(image.to_bytes().as_slice()).unwrap();
-                (
-                    EcdsaAdaptorSignature::encrypt_no_aux_rand(
-                        &secp,
-                        &arbirary_message,
-                        &alice_ecdsa_secret_key,
-                        &encryption_key,
-                    ),
-                    encryption_key,
-                )
-            })
-            .collect::<Vec<_>>();
-        ecdsa_adaptor_sigs
-    } else {
-        vec![]
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Run every combination of the given parameters several times and report the median and
+    /// spread of each phase and message size.
+    ///
+    /// Each axis takes a list like `2,8,32`, a range like `2..=10` (or `2..10` to exclude the end),
+    /// a range with a step like `10..=40:10` or a range with a factor like `2..=1024*2`. Lists and
+    /// ranges can be mixed: `1,2..=4`.
+    Sweep(SweepArgs),
+    /// Time each part of the cut-and-choose on this machine and print the cost weights as JSON.
+    ///
+    /// Pass the output to `--cost-weights` so the cut-and-choose parameters are the cheapest for
+    /// this machine.
+    Calibrate(CalibrateArgs),
+}
+
+#[derive(Args, Debug)]
+struct CalibrateArgs {
+    /// How many commitments to time each part with
+    #[clap(long, default_value_t = 64)]
+    samples: usize,
+    /// Time the DLEQ proofs that can be checked all at once
+    #[clap(long)]
+    batch_proofs: bool,
+    /// The number of threads to use when built with the `parallel` feature
+    #[clap(long)]
+    threads: Option<usize>,
+    /// Where to write the weights (defaults to stdout)
+    #[clap(long)]
+    output: Option<PathBuf>,
+}
+
+#[derive(Args, Debug)]
+struct SweepArgs {
+    /// The security parameters
+    #[clap(short, default_value = "30", value_parser = parse_axis::<u8>)]
+    s: Axis<u8>,
+    /// The numbers of outcomes
+    #[clap(long, value_parser = parse_axis::<u32>)]
+    n_outcomes: Axis<u32>,
+    /// The numbers of oracles
+    #[clap(long, value_parser = parse_axis::<u16>)]
+    n_oracles: Axis<u16>,
+    /// The thresholds. Combinations with a threshold above the number of oracles are skipped.
+    #[clap(long, value_parser = parse_axis::<u16>)]
+    threshold: Axis<u16>,
+    /// Whether to use the payout monotonicity optimization (`false`, `true` or `false,true`)
+    #[clap(long, value_delimiter = ',', default_value = "false")]
+    monotone: Vec<bool>,
+    /// Send DLEQ proofs that can be checked all at once
+    #[clap(long)]
+    batch_proofs: bool,
+    /// The bases the oracles announce (the one the cost model suggests if not given)
+    #[clap(long, value_parser = parse_axis::<u8>)]
+    base: Option<Axis<u8>>,
+    /// How many times to run each combination
+    #[clap(long, default_value_t = 5, value_parser = clap::value_parser!(u64).range(1..))]
+    repetitions: u64,
+    /// Model ECDSA adaptor signature generation too, to compare against rust-dlc
+    #[clap(long)]
+    model_ecdsa_adaptor: bool,
+    /// Run the non-interactive variant
+    #[clap(long)]
+    non_interactive: bool,
+    /// The grinding bits to allow for in non-interactive mode
+    #[clap(long, default_value_t = 40)]
+    grinding_bits: u8,
+    /// Seed the `i`th repetition of every combination with `seed + i`
+    #[clap(long)]
+    seed: Option<u64>,
+    /// The number of threads to use when built with the `parallel` feature
+    #[clap(long)]
+    threads: Option<usize>,
+    /// A JSON file of cost weights from the `calibrate` subcommand
+    #[clap(long)]
+    cost_weights: Option<PathBuf>,
+    #[clap(long, value_enum, default_value_t = SweepFormat::Csv)]
+    format: SweepFormat,
+    /// Where to write the results (defaults to stdout)
+    #[clap(long)]
+    output: Option<PathBuf>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+enum SweepFormat {
+    Csv,
+    Json,
+}
+
+/// The values to try for one parameter of a sweep.
+#[derive(Clone, Debug)]
+struct Axis<T>(Vec<T>);
+
+fn parse_axis<T: TryFrom<u64>>(spec: &str) -> Result<Axis<T>, String> {
+    let parse = |n: &str| u64::from_str(n.trim()).map_err(|e| format!("{:?}: {}", n, e));
+    let mut values = vec![];
+    for item in spec.split(',') {
+        let (start, rest) = match item.split_once("..") {
+            Some(range) => range,
+            None => {
+                values.push(parse(item)?);
+                continue;
+            }
+        };
+        let start = parse(start)?;
+        let (inclusive, rest) = match rest.strip_prefix('=') {
+            Some(rest) => (true, rest),
+            None => (false, rest),
+        };
+        let (end, next): (_, Box<dyn Fn(u64) -> u64>) = if let Some((end, step)) =
+            rest.split_once(':')
+        {
+            let step = parse(step)?;
+            if step == 0 {
+                return Err("the step of a range can't be zero".into());
+            }
+            (end, Box::new(move |value| value + step))
+        } else if let Some((end, factor)) = rest.split_once('*') {
+            let factor = parse(factor)?;
+            if factor < 2 || start == 0 {
+                return Err(
+                    "a geometric range needs a factor of at least 2 and a non-zero start".into(),
+                );
+            }
+            (end, Box::new(move |value| value * factor))
+        } else {
+            (rest, Box::new(|value| value + 1))
+        };
+        let end = parse(end)?;
+        let mut value = start;
+        while value < end || (inclusive && value == end) {
+            values.push(value);
+            value = next(value);
+        }
+    }
+    if values.is_empty() {
+        return Err(format!("{:?} has no values", spec));
+    }
+    values
+        .into_iter()
+        .map(|value| T::try_from(value).map_err(|_| format!("{} is out of range", value)))
+        .collect::<Result<_, _>>()
+        .map(Axis)
+}
+
+fn sweep(args: SweepArgs) -> anyhow::Result<()> {
+    let cost_weights = load_cost_weights(args.cost_weights)?;
+    let bases = match &args.base {
+        Some(bases) => bases.0.iter().copied().map(Some).collect(),
+        None => vec![None],
     };
-    let (alice, m1) = Alice1::new(&params);
-    let m1_encode_len = encode_len(&m1);
-    println!(
-        "End gen msg 1 elapsed: {:?} transmitted: {} (alice)",
-        start_gen_msg_1.elapsed(),
-        m1_encode_len
-    );
-    let start_gen_msg_2 = Instant::now();
-    let (bob, m2) = Bob1::new(m1, &params)?;
-    let m2_encode_len = encode_len(&m2);
-    // we imagine that verifying ECDSA adaptor signatures happens during generating msg 2
-    if args.model_ecdsa_adaptor {
-        let alice_ecdsa_public_key = PublicKey::from_secret_key(&secp, &alice_ecdsa_secret_key);
-        for (ecdsa_adaptor, encryption_key) in adaptor_sigs {
-            assert!(ecdsa_adaptor
-                .verify(
-                    &secp,
-                    &arbirary_message,
-                    &alice_ecdsa_public_key,
-                    &encryption_key
-                )
-                .is_ok());
+    let mut summaries = vec![];
+    for &security_param in &args.s.0 {
+        for &n_outcomes in &args.n_outcomes.0 {
+            for &n_oracles in &args.n_oracles.0 {
+                for &threshold in &args.threshold.0 {
+                    if threshold > n_oracles {
+                        continue;
+                    }
+                    for &monotone in &args.monotone {
+                        for &base in &bases {
+                            let config = BenchConfig {
+                                security_param,
+                                monotone,
+                                base,
+                                batch_proofs: args.batch_proofs,
+                                model_ecdsa_adaptor: args.model_ecdsa_adaptor,
+                                non_interactive: args.non_interactive,
+                                grinding_bits: args.grinding_bits,
+                                threads: args.threads,
+                                cost_weights,
+                                ..BenchConfig::new(n_outcomes, n_oracles, threshold)
+                            };
+                            eprintln!(
+                                "s: {} n_outcomes: {} n_oracles: {} threshold: {} monotone: {} base: {}",
+                                security_param,
+                                n_outcomes,
+                                n_oracles,
+                                threshold,
+                                monotone,
+                                base.map_or("auto".to_string(), |base| base.to_string())
+                            );
+                            let reports = (0..args.repetitions)
+                                .map(|repetition| {
+                                    run_benchmark(&BenchConfig {
+                                        seed: args.seed.map(|seed| seed.wrapping_add(repetition)),
+                                        ..config.clone()
+                                    })
+                                })
+                                .collect::<anyhow::Result<Vec<_>>>()?;
+                            // clap makes sure there's at least one repetition
+                            summaries.push(summarize(&reports).unwrap());
+                        }
+                    }
+                }
+            }
         }
     }
-    println!(
-        "End gen msg 2 elapsed: {:?} transmitted: {} (bob)",
-        start_gen_msg_2.elapsed(),
-        m2_encode_len
-    );
-    let start_gen_msg_3 = Instant::now();
-    let m3 = alice.receive_message(m2, secrets, &params)?;
-    let m3_encode_len = encode_len(&m3);
-    println!(
-        "End gen msg 3 elapsed: {:?} transmitted: {} (alice)",
-        start_gen_msg_3.elapsed(),
-        m3_encode_len
-    );
-    let start_processing_msg_3 = Instant::now();
-    let bob = bob.receive_message(m3, secret_images, &params)?;
-    println!(
-        "End processing msg 3 elapsed: {:?} (bob)",
-        start_processing_msg_3.elapsed()
-    );
-
-    let total_transmit_interactive = m1_encode_len + m2_encode_len + m3_encode_len;
-    let total_transmit_non_interactive = m1_encode_len + m3_encode_len;
-
-    println!(
-        "Total elapsed: {:?} sans-preprocessing: {:?} transmitted: {} non-interactive: {}",
-        start_gen_msg_1.elapsed(),
-        start_gen_msg_2.elapsed(),
-        total_transmit_interactive,
-        total_transmit_non_interactive
-    );
-
-    let outcome_index = rand::thread_rng().gen_range(0..args.n_outcomes);
-
-    let attestations = oracles
-        .iter()
-        .map(|oracle| {
-            oracle.attest(
-                &params.event_id,
-                params.n_outcome_bits() as usize,
-                outcome_index,
-            )
-        })
-        .collect();
-    println!("got attestation");
-    let scalar = bob.receive_oracle_attestation(outcome_index, attestations, &params)?;
-
-    println!("got the secret {}", scalar);
 
+    let output = match args.format {
+        SweepFormat::Csv => sweep_csv(&summaries),
+        SweepFormat::Json => serde_json::to_string_pretty(&summaries)? + "\n",
+    };
+    write_output(args.output, &output)
+}
+
+fn run_calibrate(args: CalibrateArgs) -> anyhow::Result<()> {
+    let cost_weights = calibrate(args.samples, args.batch_proofs, args.threads)?;
+    write_output(
+        args.output,
+        &(serde_json::to_string_pretty(&cost_weights)? + "\n"),
+    )
+}
+
+fn load_cost_weights(path: Option<PathBuf>) -> anyhow::Result<Option<CostWeights>> {
+    path.map(|path| {
+        let weights: CostWeights = serde_json::from_reader(File::open(path)?)?;
+        weights.validate()?;
+        Ok(weights)
+    })
+    .transpose()
+}
+
+fn write_output(path: Option<PathBuf>, output: &str) -> anyhow::Result<()> {
+    match path {
+        Some(path) => File::create(path)?.write_all(output.as_bytes())?,
+        None => print!("{}", output),
+    }
     Ok(())
 }
 
-fn encode_len(message: &impl EstimateSize) -> usize {
-    message.estimate_size()
+/// One row for each metric of each summary.
+fn sweep_csv(summaries: &[BenchSummary]) -> String {
+    let mut csv = String::from("s,n_outcomes,n_oracles,threshold,monotone,base,non_interactive,closed_proportion,bucket_size,n_commitments,soundness_bits,repetitions,metric,unit,median,min,max,mad\n");
+    for summary in summaries {
+        let config = &summary.config;
+        for metric in &summary.metrics {
+            let unit = match metric.unit {
+                Unit::Seconds => "seconds",
+                Unit::Bytes => "bytes",
+            };
+            csv += &format!(
+                "{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{}\n",
+                config.security_param,
+                config.n_outcomes,
+                config.n_oracles,
+                config.threshold,
+                config.monotone,
+                summary.params.base,
+                config.non_interactive,
+                summary.params.closed_proportion,
+                summary.params.bucket_size,
+                summary.params.n_commitments,
+                summary.params.soundness_bits,
+                summary.repetitions,
+                metric.name,
+                unit,
+                metric.median,
+                metric.min,
+                metric.max,
+                metric.mad
+            );
+        }
+    }
+    csv
+}
+
+fn main() -> anyhow::Result<()> {
+    let args = CliArgs::parse();
+    match args.command {
+        Some(Command::Sweep(sweep_args)) => return sweep(sweep_args),
+        Some(Command::Calibrate(calibrate_args)) => return run_calibrate(calibrate_args),
+        None => {}
+    }
+    let report = run_benchmark(&BenchConfig {
+        security_param: args.s,
+        // clap makes these required unless there's a subcommand
+        n_outcomes: args.n_outcomes.unwrap(),
+        n_oracles: args.n_oracles.unwrap(),
+        threshold: args.threshold.unwrap(),
+        monotone: args.monotone,
+        batch_proofs: args.batch_proofs,
+        base: args.base,
+        model_ecdsa_adaptor: args.model_ecdsa_adaptor,
+        non_interactive: args.non_interactive,
+        grinding_bits: args.grinding_bits,
+        seed: args.seed,
+        threads: args.threads,
+        cost_weights: load_cost_weights(args.cost_weights)?,
+    })?;
+    match args.format {
+        Format::Text => println!("{}", report),
+        Format::Json => println!("{}", serde_json::to_string_pretty(&report)?),
+    }
+    Ok(())
 }
@@ -1,8 +1,17 @@
-use crate::{common::Params, messages::*};
+use crate::{
+    common::Params,
+    encoding::{append_checksum, read_header, strip_checksum, write_header, Reader, Writer},
+    error::{ListKind, ProtocolError, RejectedShare, ShareRejection},
+    messages::*,
+    oracle::Attestation,
+    poly::PointPoly,
+};
 use anyhow::anyhow;
-use bls12_381::{pairing as e, G1Affine, G1Projective, G2Affine, Gt};
-use rand::{prelude::SliceRandom, RngCore};
+use bls12_381::{pairing as e, G1Affine, G1Projective, Gt, Scalar};
+use rand::{prelude::SliceRandom, CryptoRng, RngCore, SeedableRng};
+use rand_chacha::ChaCha20Rng;
 use secp256kfun::{g, marker::*, s, Point, Scalar as ChainScalar, G};
+use sha2::{digest::Digest, Sha256};
 
 pub struct Bob1 {
     commits: Vec<Commit>,
@@ -10,11 +19,13 @@ pub struct Bob1 {
 }
 
 impl Bob1 {
-    pub fn new(message: Message1, params: &Params) -> anyhow::Result<(Bob1, Message2)> {
-        if message.commits.len() != params.M() {
-            return Err(anyhow!("Alice sent wrong number of commitments"));
-        }
-        let message2 = Self::gen_message2(&message.commits, params, &mut rand::thread_rng());
+    pub fn new(
+        message: Message1,
+        params: &Params,
+        rng: &mut (impl RngCore + CryptoRng),
+    ) -> Result<(Bob1, Message2), ProtocolError> {
+        check_len(ListKind::Commitments, params.M(), message.commits.len())?;
+        let message2 = Self::gen_message2(&message.commits, params, rng);
         Ok((
             Bob1 {
                 commits: message.commits,
@@ -40,128 +51,194 @@ impl Bob1 {
         }
     }
 
+    /// Derives `Message2` from a hash of `Message1` and the contract fingerprint rather than from
+    /// Bob's randomness (Fiat-Shamir). Alice calls this herself in the non-interactive mode and Bob
+    /// calls it again to check she did.
+    ///
+    /// Since Alice can grind through commitments until she gets a challenge she likes, the
+    /// cut-and-choose parameters should come from [`compute_optimal_params_non_interactive`].
+    ///
+    /// [`compute_optimal_params_non_interactive`]: crate::common::compute_optimal_params_non_interactive
+    pub fn gen_message2_non_interactive(message1: &Message1, params: &Params) -> Message2 {
+        let mut hash = Sha256::default().chain(b"dlc-venc-pairing/non-interactive-message2");
+        hash.update(params.fingerprint());
+        hash.update(message1.encode());
+        let mut rng = ChaCha20Rng::from_seed(hash.finalize().into());
+        Self::gen_message2(&message1.commits, params, &mut rng)
+    }
+
+    /// Processes Alice's [`NonInteractiveMessage`] by recomputing the challenge she should have
+    /// used and then checking `message3` against it.
+    pub fn receive_non_interactive(
+        message: NonInteractiveMessage,
+        outcome_images: Vec<Point>,
+        params: &Params,
+    ) -> Result<Bob2, ProtocolError> {
+        check_len(
+            ListKind::Commitments,
+            params.M(),
+            message.message1.commits.len(),
+        )?;
+        let message2 = Self::gen_message2_non_interactive(&message.message1, params);
+        let bob = Bob1 {
+            commits: message.message1.commits,
+            message2,
+        };
+        bob.receive_message(message.message3, outcome_images, params)
+    }
+
     pub fn receive_message(
         self,
         message: Message3,
         outcome_images: Vec<Point>,
         params: &Params,
-    ) -> anyhow::Result<Bob2> {
+    ) -> Result<Bob2, ProtocolError> {
+        check_message3_shape(&message, &outcome_images, params)?;
         let Bob1 {
             mut commits,
             message2,
         } = self;
         let mut opened = vec![];
+        let mut closed_indexes = vec![];
         let mut i = 0;
 
         commits.retain(|commit| {
             let open_it = message2.openings.contains(&i);
             if open_it {
-                opened.push(commit.clone());
+                opened.push((i, *commit));
+            } else {
+                closed_indexes.push(i);
             }
             i += 1;
             !open_it
         });
 
-        for (commit, opening) in opened.iter().zip(message.openings.iter()) {
-            let ri_prime = opening;
-            let Ri_prime = G1Affine::generator() * ri_prime;
-            if Ri_prime != G1Projective::from(commit.C.0) {
-                return Err(anyhow!("decommitment was wrong"));
-            }
-            let ri_mapped = commit.C.1 - params.elgamal_base * ri_prime;
-            let ri = crate::common::map_Gt_to_Zq(&ri_mapped, commit.pad);
-
-            if g!(ri * G) != commit.R {
-                return Err(anyhow!(
-                    "decommitment of chain scalar didn't match chain point"
-                ));
-            }
-        }
+        check_openings(&opened, &message.openings, params)?;
 
         let mut buckets = Vec::with_capacity(params.NB());
 
         for (from, encryption) in message2.bucket_mapping.into_iter().zip(message.encryptions) {
-            buckets.push((commits[from], encryption));
+            buckets.push((closed_indexes[from], commits[from], encryption));
         }
 
-        let proof_system = crate::dleq::ProofSystem::default();
+        let proof_system = params.proof_system();
         let n_oracles = params.oracle_keys.len();
         let anticipated_attestations = (0..n_oracles)
-            .map(|oracle_index| params.iter_anticipations(oracle_index).collect::<Vec<_>>())
+            .map(|oracle_index| params.event_context().anticipations(oracle_index))
             .collect::<Vec<_>>();
 
-        let mut bit_map_encryptions = vec![];
+        let n_digits = params.n_outcome_digits() as usize;
+        let n_values = params.n_anticipations_per_digit() as usize;
+        let anticipation_for_window = |window_index: usize| {
+            let oracle_index = window_index / (n_digits * n_values);
+            let digit_index = (window_index / n_values) % n_digits;
+            let digit_value = window_index % n_values;
+            (oracle_index, digit_index, digit_value)
+        };
 
-        for (oracle_index, bits_window) in buckets
-            .chunks(
-                (params.n_outcome_bits()
-                    * params.n_anticipations_per_bit()
-                    * params.bucket_size as u32) as usize,
-            )
-            .enumerate()
-        {
-            let mut bits: Vec<Vec<_>> = vec![];
-            for (bit_index, bit_window) in bits_window
-                .chunks((params.n_anticipations_per_bit() * params.bucket_size as u32) as usize)
+        // With batchable proofs check every proof at once and only check them one by one to find
+        // the bad one if that fails.
+        let all_proofs_valid = params.batch_proofs
+            && crate::dleq::verify_eqaulity_batch(
+                &proof_system,
+                params.elgamal_base,
+                &buckets
+                    .iter()
+                    .enumerate()
+                    .map(|(i, (_, commit, (proof, encryption, _)))| {
+                        let (oracle_index, digit_index, digit_value) =
+                            anticipation_for_window(i / params.bucket_size as usize);
+                        (
+                            proof,
+                            *encryption,
+                            anticipated_attestations[oracle_index][digit_index][digit_value],
+                            commit.C,
+                        )
+                    })
+                    .collect::<Vec<_>>(),
+            );
+
+        let mut digit_value_buckets = crate::parallel::try_map(
+            buckets
+                .chunks(params.bucket_size as usize)
                 .enumerate()
-            {
-                let mut bit_values = vec![];
-                for (bit_value_index, bit_value_window) in
-                    bit_window.chunks((params.bucket_size) as usize).enumerate()
-                {
-                    let T = message.bit_map_images[oracle_index][bit_index][bit_value_index];
-                    let anticipated_attestation =
-                        anticipated_attestations[oracle_index][bit_index][bit_value_index];
+                .collect(),
+            |(window_index, digit_value_window)| {
+                let (oracle_index, digit_index, digit_value) =
+                    anticipation_for_window(window_index);
+                let T = message.bit_map_images[oracle_index][digit_index][digit_value];
+                let anticipated_attestation =
+                    anticipated_attestations[oracle_index][digit_index][digit_value];
 
-                    let mut bit_value_bucket = vec![];
+                let mut digit_value_bucket = vec![];
 
-                    for (commit, (proof, encryption, padded_T)) in bit_value_window {
-                        if !crate::dleq::verify_eqaulity(
+                for (commitment_index, commit, (proof, encryption, padded_T)) in digit_value_window
+                {
+                    if !all_proofs_valid
+                        && !crate::dleq::verify_eqaulity(
                             &proof_system,
                             proof,
                             *encryption,
                             anticipated_attestation,
                             params.elgamal_base,
                             commit.C,
-                        ) {
-                            return Err(anyhow!(
-                                "proof of equality between ciphertext and commitment was invalid"
-                            ));
-                        }
-
-                        if g!(T + commit.R) != g!(padded_T * G) {
-                            return Err(anyhow!("padded sig wasn't valid"));
-                        }
+                        )
+                    {
+                        return Err(ProtocolError::InvalidEncryptionProof {
+                            commitment_index: *commitment_index,
+                            oracle_index,
+                            digit_index,
+                        });
+                    }
 
-                        bit_value_bucket.push(((commit.C.0, *encryption), *padded_T, commit.pad));
+                    if g!(T + commit.R) != g!(padded_T * G) {
+                        return Err(ProtocolError::InvalidPaddedBitMapSecret {
+                            commitment_index: *commitment_index,
+                            oracle_index,
+                            digit_index,
+                        });
                     }
 
-                    bit_values.push((bit_value_bucket, T))
+                    digit_value_bucket.push(((commit.C.0, *encryption), *padded_T, commit.pad));
                 }
-                bits.push(bit_values.try_into().unwrap());
-            }
-            bit_map_encryptions.push(bits);
-        }
+
+                Ok((digit_value_bucket, T))
+            },
+        )?
+        .into_iter();
+
+        let bit_map_encryptions = (0..n_oracles)
+            .map(|_| {
+                (0..n_digits)
+                    .map(|_| digit_value_buckets.by_ref().take(n_values).collect())
+                    .collect()
+            })
+            .collect();
+
+        let share_polys = message
+            .polys
+            .into_iter()
+            .zip(&outcome_images)
+            .map(|(mut poly, outcome_image)| {
+                poly.push_front(*outcome_image);
+                poly
+            })
+            .collect::<Vec<_>>();
 
         for (oracle_index, secret_share_pads) in
             message.secret_share_pads_by_oracle.iter().enumerate()
         {
-            let pad_images =
-                compute_pad_images(&message.bit_map_images[oracle_index], params.monotone);
-            assert!(pad_images.len() >= params.n_outcomes as usize);
-            assert_eq!(secret_share_pads.len(), params.n_outcomes as usize);
+            let pad_images = compute_pad_images(&message.bit_map_images[oracle_index], params.base);
+            debug_assert!(pad_images.len() >= params.n_outcomes as usize);
             for (outcome_index, (outcome_pad, expected_outcome_pad)) in
                 secret_share_pads.iter().zip(pad_images).enumerate()
             {
-                let mut poly = message.polys[outcome_index].clone();
-                poly.push_front(outcome_images[outcome_index]);
-                let secret_share_image = poly.eval((oracle_index + 1) as u32);
+                let secret_share_image = share_polys[outcome_index].eval((oracle_index + 1) as u32);
                 if g!(outcome_pad * G) != g!(expected_outcome_pad + secret_share_image) {
-                    return Err(anyhow!(
-                        "outcome pad for outcome {} and oracle {} was wrong",
+                    return Err(ProtocolError::InvalidOutcomePad {
+                        oracle_index,
                         outcome_index,
-                        oracle_index
-                    ));
+                    });
                 }
             }
         }
@@ -169,160 +246,904 @@ impl Bob1 {
         Ok(Bob2 {
             bit_map_encryptions,
             secret_share_pads_by_oracle: message.secret_share_pads_by_oracle,
+            share_polys,
             outcome_images,
         })
     }
 }
 
+/// Checks that each opened commitment is consistent with the `ri'` Alice revealed for it.
+///
+/// The Gt exponentiation and hash that recover `ri` have to be done for each opening but the G1 and
+/// secp256k1 equations are checked all at once with a random linear combination:
+///
+/// `(Σ aᵢ·ri')·g == Σ aᵢ·Cᵢ.0` and `(Σ aᵢ·riᵢ)·G == Σ aᵢ·Rᵢ`
+///
+/// If either fails we go through them one by one to find the culprit.
+pub(crate) fn check_openings(
+    opened: &[(usize, Commit)],
+    openings: &[Scalar],
+    params: &Params,
+) -> Result<(), ProtocolError> {
+    let ris = crate::parallel::map(
+        opened.iter().zip(openings).collect(),
+        |((_, commit), ri_prime)| {
+            let ri_mapped = commit.C.1 - params.elgamal_base * ri_prime;
+            crate::common::map_Gt_to_Zq(&ri_mapped, commit.pad)
+        },
+    );
+
+    let mut writer = Writer::default();
+    for ((_, commit), ri_prime) in opened.iter().zip(openings) {
+        commit.write(&mut writer);
+        writer.scalar(ri_prime);
+    }
+    let seed = Sha256::default()
+        .chain(b"dlc-venc-pairing/batch-openings")
+        .chain(writer.finish())
+        .finalize();
+    let coefficients = (0..opened.len())
+        .map(|i| crate::msm::batch_coefficient(&seed, i as u32))
+        .collect::<Vec<_>>();
+    let chain_coefficients = coefficients
+        .iter()
+        .map(|coefficient| {
+            // the coefficients are only 128 bits so they mean the same thing in both fields
+            let mut bytes = coefficient.to_bytes();
+            bytes.reverse();
+            ChainScalar::from_bytes_mod_order(bytes).mark::<Public>()
+        })
+        .collect::<Vec<_>>();
+
+    let C0s = opened
+        .iter()
+        .map(|(_, commit)| G1Projective::from(commit.C.0))
+        .collect::<Vec<_>>();
+    let combined_opening: Scalar = coefficients
+        .iter()
+        .zip(openings)
+        .map(|(coefficient, ri_prime)| coefficient * ri_prime)
+        .sum();
+    let g1_valid = G1Affine::generator() * combined_opening == crate::msm::msm(&coefficients, &C0s);
+
+    let combined_ri = chain_coefficients
+        .iter()
+        .zip(&ris)
+        .fold(ChainScalar::zero(), |acc, (coefficient, ri)| {
+            s!(acc + coefficient * ri)
+        });
+    let Rs = opened
+        .iter()
+        .map(|(_, commit)| commit.R)
+        .collect::<Vec<_>>();
+    let chain_valid = g!(combined_ri * G) == secp256kfun::op::lincomb(&chain_coefficients, &Rs);
+
+    if g1_valid && chain_valid {
+        return Ok(());
+    }
+
+    for (((commitment_index, commit), ri_prime), ri) in opened.iter().zip(openings).zip(&ris) {
+        if G1Affine::generator() * ri_prime != G1Projective::from(commit.C.0) {
+            return Err(ProtocolError::InvalidOpening {
+                commitment_index: *commitment_index,
+            });
+        }
+        if g!(ri * G) != commit.R {
+            return Err(ProtocolError::InvalidOpeningChainPoint {
+                commitment_index: *commitment_index,
+            });
+        }
+    }
+    unreachable!("a batch check can only fail if one of the individual checks does")
+}
+
+fn check_len(list: ListKind, expected: usize, got: usize) -> Result<(), ProtocolError> {
+    if expected != got {
+        return Err(ProtocolError::WrongLength {
+            list,
+            expected,
+            got,
+        });
+    }
+    Ok(())
+}
+
+/// Checks every list in `message` has the length the `Params` say it should so that we can index
+/// into them without worrying.
+fn check_message3_shape(
+    message: &Message3,
+    outcome_images: &[Point],
+    params: &Params,
+) -> Result<(), ProtocolError> {
+    let n_oracles = params.oracle_keys.len();
+    let n_outcomes = params.n_outcomes as usize;
+    let n_digits = params.n_outcome_digits() as usize;
+    check_len(ListKind::OutcomeImages, n_outcomes, outcome_images.len())?;
+    check_len(
+        ListKind::Encryptions,
+        params.NB(),
+        message.encryptions.len(),
+    )?;
+    check_len(
+        ListKind::Openings,
+        params.num_openings(),
+        message.openings.len(),
+    )?;
+    check_len(ListKind::Polys, n_outcomes, message.polys.len())?;
+    for (outcome_index, poly) in message.polys.iter().enumerate() {
+        check_len(
+            ListKind::Poly { outcome_index },
+            params.threshold.saturating_sub(1) as usize,
+            poly.poly_len(),
+        )?;
+    }
+    check_len(
+        ListKind::BitMapImages,
+        n_oracles,
+        message.bit_map_images.len(),
+    )?;
+    for (oracle_index, oracle_images) in message.bit_map_images.iter().enumerate() {
+        check_len(
+            ListKind::OracleBitMapImages { oracle_index },
+            n_digits,
+            oracle_images.len(),
+        )?;
+        for (digit_index, digit_images) in oracle_images.iter().enumerate() {
+            check_len(
+                ListKind::DigitValueBitMapImages {
+                    oracle_index,
+                    digit_index,
+                },
+                params.n_anticipations_per_digit() as usize,
+                digit_images.len(),
+            )?;
+        }
+    }
+    check_len(
+        ListKind::SecretSharePads,
+        n_oracles,
+        message.secret_share_pads_by_oracle.len(),
+    )?;
+    for (oracle_index, pads) in message.secret_share_pads_by_oracle.iter().enumerate() {
+        check_len(
+            ListKind::OracleSecretSharePads { oracle_index },
+            n_outcomes,
+            pads.len(),
+        )?;
+    }
+    Ok(())
+}
+
+// a bucket of encryptions of `ri_mapped` along with the padded bit map secret and the pad that maps
+// the decryption back to `ri`.
+type EncryptionBucket = Vec<((G1Affine, Gt), ChainScalar<Public, Zero>, [u8; 32])>;
+
 pub struct Bob2 {
     // For every oracle
     bit_map_encryptions: Vec<
-        // For every outcome bit
+        // For every outcome digit
         Vec<
-            // 1 or 2 bit values depending on whether we're monotone or not
+            // Every digit value, except the highest one if we're monotone
             Vec<(
-                // a bucket of encryptions
-                Vec<((G1Affine, Gt), ChainScalar<Public, Zero>, [u8; 32])>,
+                EncryptionBucket,
                 // The image of the bit map that is encrypted
                 Point,
             )>,
         >,
     >,
-    // The image of the secret that should be revealed for each outcome
+    // For every oracle, the secret share of each outcome padded by the sum of the bit map secrets
     secret_share_pads_by_oracle: Vec<Vec<ChainScalar<Public, Zero>>>,
+    // For every outcome, the polynomial whose evaluation at `oracle_index + 1` is the image of that
+    // oracle's secret share (its constant term is the outcome image)
+    share_polys: Vec<PointPoly>,
+    // The image of the secret that should be revealed for each outcome
     outcome_images: Vec<Point>,
 }
 
 impl Bob2 {
+    /// Decrypts the secret for `outcome_index` from the oracles' attestations to it. Attestations
+    /// can be given in any order. Only the first one from each oracle that decrypts a share is used
+    /// so a bad attestation under an oracle's key can't take the place of its real one.
+    ///
+    /// Each decrypted share is checked against its image (computed from `polys` and the outcome
+    /// image) before it is used, so as long as `threshold` oracles attested honestly we recover the
+    /// secret. The attestations we couldn't use are reported alongside it.
     pub fn receive_oracle_attestation(
-        self,
+        &self,
         outcome_index: u32,
-        attestations: Vec<Vec<G2Affine>>,
+        attestations: &[Attestation],
         params: &Params,
-    ) -> anyhow::Result<ChainScalar<Public, Zero>> {
-        let outcome_bits = crate::common::to_bits(outcome_index, params.n_outcome_bits() as usize);
+    ) -> Result<RecoveredSecret, ProtocolError> {
+        if outcome_index >= params.n_outcomes {
+            return Err(ProtocolError::OutcomeOutOfRange {
+                outcome_index,
+                n_outcomes: params.n_outcomes,
+            });
+        }
+        let outcome_digits = crate::common::to_digits(
+            outcome_index,
+            params.base,
+            params.n_outcome_digits() as usize,
+        );
+        let mut attested = vec![false; params.oracle_keys.len()];
         let mut secret_shares = vec![];
-        for (oracle_index, bit_attestations) in attestations.into_iter().enumerate() {
-            assert_eq!(
-                outcome_bits.len(),
-                bit_attestations.len(),
-                "attestation for oracle didn't have the right number of signatures"
-            );
+        let mut rejected = vec![];
+        let mut to_decrypt = vec![];
+        for (attestation_index, attestation) in attestations.iter().enumerate() {
+            let oracle_index = match params
+                .oracle_keys
+                .iter()
+                .position(|key| key.public_key == attestation.public_key)
+            {
+                Some(oracle_index) => oracle_index,
+                None => {
+                    rejected.push(RejectedShare {
+                        attestation_index,
+                        oracle_index: None,
+                        reason: ShareRejection::UnknownOracle,
+                    });
+                    continue;
+                }
+            };
+            let reject = |reason| RejectedShare {
+                attestation_index,
+                oracle_index: Some(oracle_index),
+                reason,
+            };
+            if attestation.digit_signatures.len() != outcome_digits.len() {
+                rejected.push(reject(ShareRejection::WrongSignatureCount {
+                    expected: outcome_digits.len(),
+                    got: attestation.digit_signatures.len(),
+                }));
+                continue;
+            }
+            to_decrypt.push((attestation_index, oracle_index, attestation));
+        }
 
-            let bit_map_pads = outcome_bits
+        let decrypted = crate::parallel::map(
+            to_decrypt,
+            |(attestation_index, oracle_index, attestation)| {
+                let decrypted = self.decrypt_share(
+                    oracle_index,
+                    outcome_index,
+                    &outcome_digits,
+                    attestation,
+                    params,
+                );
+                (attestation_index, oracle_index, decrypted)
+            },
+        );
+        // these are still in the order they were given
+        for (attestation_index, oracle_index, decrypted) in decrypted {
+            let reason = match decrypted {
+                Ok(_) if attested[oracle_index] => ShareRejection::DuplicateAttestation,
+                Ok(secret_share) => {
+                    attested[oracle_index] = true;
+                    secret_shares.push((
+                        ChainScalar::from(oracle_index as u32 + 1).expect_nonzero("added 1"),
+                        secret_share,
+                    ));
+                    continue;
+                }
+                Err(reason) => reason,
+            };
+            rejected.push(RejectedShare {
+                attestation_index,
+                oracle_index: Some(oracle_index),
+                reason,
+            });
+        }
+        rejected.sort_by_key(|rejection| rejection.attestation_index);
+
+        if secret_shares.len() < params.threshold as usize {
+            return Err(ProtocolError::NotEnoughShares {
+                got: secret_shares.len(),
+                threshold: params.threshold as usize,
+                rejected,
+            });
+        }
+
+        let shares = &secret_shares[0..params.threshold as usize];
+
+        let secret = shares.iter().fold(s!(0), |acc, (x_j, y_j)| {
+            let x_ms = shares
                 .iter()
-                .zip(bit_attestations)
-                .enumerate()
-                .filter(|(_, (bit_value, _))| !params.monotone || **bit_value == false)
-                .map(|(bit_index, (bit_value, bit_attestation))| {
-                    if !params.verify_bls_sig(
-                        oracle_index,
-                        bit_index as u32,
-                        *bit_value,
-                        bit_attestation,
-                    ) {
-                        eprintln!(
-                            "BLS signature from oracle {} on bit {} was invalid",
-                            oracle_index, bit_index
-                        );
-                        return None;
+                .map(|(x_m, _)| x_m)
+                .filter(|x_m| x_m != &x_j)
+                .collect::<Vec<_>>();
+            let (num, denom) = x_ms.iter().fold((s!(1), s!(1)), |(acc_n, acc_d), x_m| {
+                (
+                    s!(acc_n * { x_m }),
+                    s!(acc_d * ({ x_m } - x_j)).expect_nonzero("unreachable"),
+                )
+            });
+            let lagrange_coeff = s!(num * { denom.invert() });
+            s!(acc + lagrange_coeff * y_j)
+        });
+        // can't happen since every share was checked against the polynomial but just in case
+        if g!(secret * G) != self.outcome_images[outcome_index as usize] {
+            return Err(ProtocolError::WrongSecretRecovered { outcome_index });
+        }
+
+        Ok(RecoveredSecret {
+            secret: secret.mark::<Public>(),
+            rejected,
+        })
+    }
+
+    fn decrypt_share(
+        &self,
+        oracle_index: usize,
+        outcome_index: u32,
+        outcome_digits: &[u32],
+        attestation: &Attestation,
+        params: &Params,
+    ) -> Result<ChainScalar<Secret, Zero>, ShareRejection> {
+        if attestation.event_id != params.event_id || attestation.outcome_index != outcome_index {
+            return Err(ShareRejection::WrongOutcome {
+                outcome_index: attestation.outcome_index,
+            });
+        }
+        if !attestation.verify_with_context(params.event_context()) {
+            return Err(ShareRejection::InvalidAttestation);
+        }
+
+        let mut secret_share_pad = s!(0);
+        // in the monotone mode the highest value of a digit has nothing to decrypt
+        let n_values = params.n_anticipations_per_digit();
+        for (digit_index, (digit_value, digit_attestation)) in outcome_digits
+            .iter()
+            .zip(&attestation.digit_signatures)
+            .enumerate()
+            .filter(|(_, (digit_value, _))| **digit_value < n_values)
+        {
+            let (outcome_digit_bucket, expected_bit_map_image) =
+                &self.bit_map_encryptions[oracle_index][digit_index][*digit_value as usize];
+            // Only one encryption in the bucket has to be right
+            let bit_map_secret = outcome_digit_bucket
+                .iter()
+                .find_map(|(encryption, padded_bit_map_secret, pad)| {
+                    let ri_mapped = encryption.1 - e(&encryption.0, digit_attestation);
+                    let ri = crate::common::map_Gt_to_Zq(&ri_mapped, *pad);
+                    let bit_map_secret = s!(padded_bit_map_secret - ri).mark::<Public>();
+                    if &g!(bit_map_secret * G) == expected_bit_map_image {
+                        Some(bit_map_secret)
+                    } else {
+                        None
                     }
-                    let (outcome_bit_bucket, expected_bit_map_image) =
-                        &self.bit_map_encryptions[oracle_index][bit_index][*bit_value as usize];
-                    outcome_bit_bucket.iter().find_map(
-                        |(encryption, padded_bit_map_secret, pad)| {
-                            let ri_mapped = encryption.1 - e(&encryption.0, &bit_attestation);
-                            let ri = crate::common::map_Gt_to_Zq(&ri_mapped, *pad);
-                            let bit_map_secret = s!(padded_bit_map_secret - ri).mark::<Public>();
-                            let got_bit_map_image = g!(bit_map_secret * G);
-                            if &got_bit_map_image == expected_bit_map_image {
-                                Some(bit_map_secret)
-                            } else {
-                                eprintln!(
-                                    "we didn't decrypt what was expected -- ignoring that share"
-                                );
-                                None
-                            }
-                        },
-                    )
                 })
-                .collect::<Option<Vec<_>>>();
+                .ok_or(ShareRejection::DecryptionFailed { digit_index })?;
+            secret_share_pad = s!(secret_share_pad + bit_map_secret);
+        }
 
-            let secret_share_pad = match bit_map_pads {
-                Some(bit_map_pads) => bit_map_pads
-                    .into_iter()
-                    .fold(s!(0), |acc, pad| s!(acc + pad)),
-                None => continue,
-            };
+        let secret_share =
+            s!(
+                { self.secret_share_pads_by_oracle[oracle_index][outcome_index as usize] }
+                    - secret_share_pad
+            );
+        let expected_image = self.share_polys[outcome_index as usize].eval(oracle_index as u32 + 1);
+        if g!(secret_share * G) != expected_image {
+            return Err(ShareRejection::WrongShare);
+        }
 
-            let secret_share =
-                s!(
-                    { self.secret_share_pads_by_oracle[oracle_index][outcome_index as usize] }
-                        - secret_share_pad
-                );
+        Ok(secret_share)
+    }
+}
 
-            secret_shares.push((
-                ChainScalar::from(oracle_index as u32 + 1).expect_nonzero("added 1"),
-                secret_share,
-            ));
+impl Bob2 {
+    /// Encodes the state so Bob can store it until the oracles attest. The encoding is bound to the
+    /// contract's fingerprint and ends with a checksum.
+    pub fn to_bytes(&self, params: &Params) -> Vec<u8> {
+        let mut writer = Writer::default();
+        write_header(&mut writer, BOB2_TAG);
+        writer.bytes(&params.fingerprint());
+        writer.list(&self.bit_map_encryptions, |writer, digits| {
+            writer.list(digits, |writer, digit_values| {
+                writer.list(digit_values, |writer, (bucket, bit_map_image)| {
+                    writer.list(bucket, |writer, ((c1, c2), padded_bit_map_secret, pad)| {
+                        writer.g1(c1);
+                        writer.gt(c2);
+                        writer.chain_scalar(padded_bit_map_secret);
+                        writer.bytes(pad);
+                    });
+                    writer.point(bit_map_image);
+                })
+            })
+        });
+        writer.list(&self.secret_share_pads_by_oracle, |writer, pads| {
+            writer.list(pads, |writer, pad| writer.chain_scalar(pad))
+        });
+        writer.list(&self.share_polys, |writer, poly| {
+            writer.list(poly.points(), |writer, point| writer.point(point))
+        });
+        writer.list(&self.outcome_images, |writer, image| writer.point(image));
+        append_checksum(writer.finish())
+    }
+
+    /// Loads state saved with [`Bob2::to_bytes`]. It fails if the data is corrupted, was saved for
+    /// a different contract or doesn't have the shape `params` requires.
+    pub fn from_bytes(bytes: &[u8], params: &Params) -> anyhow::Result<Self> {
+        let mut reader = Reader::new(strip_checksum(bytes)?);
+        read_header(&mut reader, BOB2_TAG)?;
+        if reader.array::<32>()? != params.fingerprint() {
+            return Err(anyhow!("the state was saved for a different contract"));
         }
+        let bit_map_encryptions = reader.list(|reader| {
+            reader.list(|reader| {
+                reader.list(|reader| {
+                    let bucket = reader.list(|reader| {
+                        Ok((
+                            (reader.g1()?, reader.gt()?),
+                            reader.chain_scalar()?,
+                            reader.array()?,
+                        ))
+                    })?;
+                    Ok((bucket, reader.point()?))
+                })
+            })
+        })?;
+        let secret_share_pads_by_oracle =
+            reader.list(|reader| reader.list(Reader::chain_scalar))?;
+        let share_polys = reader.list(|reader| Ok(PointPoly::new(reader.list(Reader::point)?)))?;
+        let outcome_images = reader.list(Reader::point)?;
+        reader.finish()?;
 
-        if secret_shares.len() >= params.threshold as usize {
-            let shares = &secret_shares[0..params.threshold as usize];
+        let bob = Bob2 {
+            bit_map_encryptions,
+            secret_share_pads_by_oracle,
+            share_polys,
+            outcome_images,
+        };
+        bob.check_shape(params)?;
+        Ok(bob)
+    }
 
-            let secret = shares.iter().fold(s!(0), |acc, (x_j, y_j)| {
-                let x_ms = shares
-                    .iter()
-                    .map(|(x_m, _)| x_m)
-                    .filter(|x_m| x_m != &x_j)
-                    .collect::<Vec<_>>();
-                let (num, denom) = x_ms.iter().fold((s!(1), s!(1)), |(acc_n, acc_d), x_m| {
-                    (
-                        s!(acc_n * { x_m }),
-                        s!(acc_d * ({ x_m } - x_j)).expect_nonzero("unreachable"),
-                    )
-                });
-                let lagrange_coeff = s!(num * { denom.invert() });
-                s!(acc + lagrange_coeff * y_j)
-            });
-            if g!(secret * G) != self.outcome_images[outcome_index as usize] {
-                return Err(anyhow!("the secret we recovered was wrong"));
+    fn check_shape(&self, params: &Params) -> Result<(), ProtocolError> {
+        let n_oracles = params.oracle_keys.len();
+        let n_outcomes = params.n_outcomes as usize;
+        check_len(
+            ListKind::BitMapImages,
+            n_oracles,
+            self.bit_map_encryptions.len(),
+        )?;
+        for (oracle_index, digits) in self.bit_map_encryptions.iter().enumerate() {
+            check_len(
+                ListKind::OracleBitMapImages { oracle_index },
+                params.n_outcome_digits() as usize,
+                digits.len(),
+            )?;
+            for (digit_index, digit_values) in digits.iter().enumerate() {
+                check_len(
+                    ListKind::DigitValueBitMapImages {
+                        oracle_index,
+                        digit_index,
+                    },
+                    params.n_anticipations_per_digit() as usize,
+                    digit_values.len(),
+                )?;
+                for (digit_value, (bucket, _)) in digit_values.iter().enumerate() {
+                    check_len(
+                        ListKind::Bucket {
+                            oracle_index,
+                            digit_index,
+                            digit_value,
+                        },
+                        params.bucket_size as usize,
+                        bucket.len(),
+                    )?;
+                }
             }
-
-            Ok(secret.mark::<Public>())
-        } else {
-            Err(anyhow!("not enough shares to reconstruct secret!"))
         }
+        check_len(
+            ListKind::SecretSharePads,
+            n_oracles,
+            self.secret_share_pads_by_oracle.len(),
+        )?;
+        for (oracle_index, pads) in self.secret_share_pads_by_oracle.iter().enumerate() {
+            check_len(
+                ListKind::OracleSecretSharePads { oracle_index },
+                n_outcomes,
+                pads.len(),
+            )?;
+        }
+        check_len(ListKind::Polys, n_outcomes, self.share_polys.len())?;
+        for (outcome_index, poly) in self.share_polys.iter().enumerate() {
+            // unlike `Message3::polys` these include the outcome image as the constant term
+            check_len(
+                ListKind::Poly { outcome_index },
+                params.threshold as usize,
+                poly.poly_len(),
+            )?;
+        }
+        check_len(
+            ListKind::OutcomeImages,
+            n_outcomes,
+            self.outcome_images.len(),
+        )
     }
 }
 
-fn compute_pad_images(pads: &[Vec<Point>], monotone: bool) -> Vec<Point<Jacobian, Public, Zero>> {
-    _compute_pad_images(
-        pads.len() - 1,
-        Point::zero().mark::<Jacobian>(),
-        pads,
-        monotone,
-    )
+/// The secret Bob recovered along with the oracles whose shares he couldn't use.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecoveredSecret {
+    pub secret: ChainScalar<Public, Zero>,
+    pub rejected: Vec<RejectedShare>,
+}
+
+fn compute_pad_images(pads: &[Vec<Point>], base: u8) -> Vec<Point<Jacobian, Public, Zero>> {
+    _compute_pad_images(pads.len() - 1, Point::zero().mark::<Jacobian>(), pads, base)
 }
 
 fn _compute_pad_images(
-    cur_bit: usize,
+    cur_digit: usize,
     acc: Point<Jacobian, Public, Zero>,
     pads: &[Vec<Point>],
-    monotone: bool,
+    base: u8,
 ) -> Vec<Point<Jacobian, Public, Zero>> {
-    let zero = g!(acc + { pads[cur_bit][0] });
-    let one = if monotone {
-        acc
-    } else {
-        g!(acc + { pads[cur_bit][1] })
-    };
+    let values = (0..base as usize).map(|digit_value| match pads[cur_digit].get(digit_value) {
+        Some(pad) => g!(acc + pad),
+        None => acc,
+    });
 
-    if cur_bit == 0 {
-        vec![zero, one]
+    if cur_digit == 0 {
+        values.collect()
     } else {
-        let mut children = _compute_pad_images(cur_bit - 1, zero, pads, monotone);
-        children.extend(_compute_pad_images(cur_bit - 1, one, pads, monotone));
-        children
+        values
+            .flat_map(|acc| _compute_pad_images(cur_digit - 1, acc, pads, base))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use super::*;
+    use crate::{alice::Alice1, oracle::Oracle};
+
+    pub(crate) fn test_params(oracles: &[Oracle], threshold: u16) -> Params {
+        crate::common::ParamsBuilder::new(
+            oracles.iter().map(Oracle::key).collect(),
+            "test",
+            4,
+            threshold,
+        )
+        .cut_and_choose(0.5, 2)
+        .build()
+        .unwrap()
+    }
+
+    /// A random secret for each outcome and both parties after the first round.
+    pub(crate) struct TestSetup {
+        pub secrets: Vec<ChainScalar>,
+        pub secret_images: Vec<Point>,
+        pub alice: Alice1,
+        pub message1: Message1,
+        pub bob: Bob1,
+        pub message2: Message2,
+    }
+
+    pub(crate) fn test_setup(params: &Params, rng: &mut (impl RngCore + CryptoRng)) -> TestSetup {
+        let secrets = (0..params.n_outcomes)
+            .map(|_| ChainScalar::random(rng))
+            .collect::<Vec<_>>();
+        let secret_images = secrets
+            .iter()
+            .map(|secret| g!(secret * G).normalize())
+            .collect();
+        let (alice, message1) = Alice1::new(params, rng);
+        let (bob, message2) = Bob1::new(message1.clone(), params, rng).unwrap();
+        TestSetup {
+            secrets,
+            secret_images,
+            alice,
+            message1,
+            bob,
+            message2,
+        }
+    }
+
+    impl TestSetup {
+        /// Runs the rest of the protocol honestly.
+        pub fn finish(
+            self,
+            params: &Params,
+            rng: &mut (impl RngCore + CryptoRng),
+        ) -> (Vec<ChainScalar>, Bob2) {
+            let message3 = self
+                .alice
+                .receive_message(self.message2, self.secrets.clone(), params, rng)
+                .unwrap();
+            let bob = self
+                .bob
+                .receive_message(message3, self.secret_images, params)
+                .unwrap();
+            (self.secrets, bob)
+        }
+    }
+
+    #[test]
+    fn non_interactive_roundtrip() {
+        let rng = &mut rand::thread_rng();
+        let oracles = [Oracle::random(rng)];
+        let params = test_params(&oracles, 1);
+        let setup = test_setup(&params, rng);
+        let message = setup
+            .alice
+            .non_interactive_message(setup.secrets.clone(), &params, rng)
+            .unwrap();
+
+        let mut tampered = message.clone();
+        tampered.message1.commits.swap(0, 1);
+        assert!(
+            Bob1::receive_non_interactive(tampered, setup.secret_images.clone(), &params).is_err()
+        );
+
+        let bob = Bob1::receive_non_interactive(message, setup.secret_images, &params).unwrap();
+        let attestation = oracles[0].attest(
+            &params.event_id,
+            params.base(),
+            params.n_outcome_digits() as usize,
+            2,
+        );
+        let recovered = bob
+            .receive_oracle_attestation(2, &[attestation], &params)
+            .unwrap();
+        assert_eq!(
+            recovered.secret,
+            setup.secrets[2].clone().mark::<(Public, Zero)>()
+        );
+        assert!(recovered.rejected.is_empty());
+    }
+
+    #[test]
+    fn monotone_base_three_roundtrip() {
+        let rng = &mut rand::thread_rng();
+        let oracles = [Oracle::random(rng)];
+        let params = crate::common::ParamsBuilder::new(
+            oracles.iter().map(Oracle::key).collect(),
+            "test",
+            7,
+            1,
+        )
+        .base(3)
+        .monotone(true)
+        .cut_and_choose(0.5, 1)
+        .build()
+        .unwrap();
+        assert_eq!(params.n_anticipations_per_oracle(), 4);
+        let (secrets, bob) = test_setup(&params, rng).finish(&params, rng);
+        // 5 is 12 in base 3 so its top digit has nothing to decrypt
+        for outcome_index in [0, 5, 6] {
+            let attestation = oracles[0].attest(&params.event_id, 3, 2, outcome_index);
+            let recovered = bob
+                .receive_oracle_attestation(outcome_index, &[attestation], &params)
+                .unwrap();
+            assert_eq!(
+                recovered.secret,
+                secrets[outcome_index as usize]
+                    .clone()
+                    .mark::<(Public, Zero)>()
+            );
+        }
+    }
+
+    #[test]
+    fn bad_shares_are_rejected() {
+        let rng = &mut rand::thread_rng();
+        let oracles = (0..4).map(|_| Oracle::random(rng)).collect::<Vec<_>>();
+        let params = test_params(&oracles, 2);
+        let (secrets, mut bob) = test_setup(&params, rng).finish(&params, rng);
+        // pretend Alice managed to give oracle 1 a bad share of outcome 2
+        let bad_pad = &mut bob.secret_share_pads_by_oracle[1][2];
+        *bad_pad = s!({ &*bad_pad } + { ChainScalar::one() }).mark::<Public>();
+
+        let (base, n_digits) = (params.base(), params.n_outcome_digits() as usize);
+        let attestations = [
+            oracles[0].attest(&params.event_id, base, n_digits, 1),
+            oracles[1].attest(&params.event_id, base, n_digits, 2),
+            oracles[3].attest(&params.event_id, base, n_digits, 2),
+        ];
+        assert_eq!(
+            bob.receive_oracle_attestation(2, &attestations, &params),
+            Err(ProtocolError::NotEnoughShares {
+                got: 1,
+                threshold: 2,
+                rejected: vec![
+                    RejectedShare {
+                        attestation_index: 0,
+                        oracle_index: Some(0),
+                        reason: ShareRejection::WrongOutcome { outcome_index: 1 }
+                    },
+                    RejectedShare {
+                        attestation_index: 1,
+                        oracle_index: Some(1),
+                        reason: ShareRejection::WrongShare
+                    },
+                ]
+            })
+        );
+
+        let mut attestations = attestations.to_vec();
+        attestations.push(oracles[2].attest(&params.event_id, base, n_digits, 2));
+        let recovered = bob
+            .receive_oracle_attestation(2, &attestations, &params)
+            .unwrap();
+        assert_eq!(
+            recovered.secret,
+            secrets[2].clone().mark::<(Public, Zero)>()
+        );
+        assert_eq!(recovered.rejected.len(), 2);
+    }
+
+    #[test]
+    fn bob2_persistence() {
+        let rng = &mut rand::thread_rng();
+        let oracles = (0..2).map(|_| Oracle::random(rng)).collect::<Vec<_>>();
+        let params = test_params(&oracles, 2);
+        let (_, bob) = test_setup(&params, rng).finish(&params, rng);
+
+        let saved = bob.to_bytes(&params);
+        let restored = Bob2::from_bytes(&saved, &params).unwrap();
+        assert_eq!(restored.to_bytes(&params), saved);
+        let mut corrupted = saved.clone();
+        corrupted[100] ^= 1;
+        assert!(Bob2::from_bytes(&corrupted, &params).is_err());
+        let other_params = test_params(&oracles, 1);
+        assert!(Bob2::from_bytes(&saved, &other_params).is_err());
+
+        // these have a valid checksum but the wrong shape
+        let mut short_bucket = Bob2::from_bytes(&saved, &params).unwrap();
+        short_bucket.bit_map_encryptions[1][0][1].0.pop();
+        assert!(Bob2::from_bytes(&short_bucket.to_bytes(&params), &params).is_err());
+        let mut short_poly = Bob2::from_bytes(&saved, &params).unwrap();
+        short_poly.share_polys[3] = PointPoly::new(vec![short_poly.outcome_images[3]]);
+        assert!(Bob2::from_bytes(&short_poly.to_bytes(&params), &params).is_err());
+    }
+
+    #[test]
+    fn malformed_attestations_are_skipped() {
+        let rng = &mut rand::thread_rng();
+        let oracles = (0..3).map(|_| Oracle::random(rng)).collect::<Vec<_>>();
+        let params = test_params(&oracles, 2);
+        let (secrets, bob) = test_setup(&params, rng).finish(&params, rng);
+
+        let (base, n_digits) = (params.base(), params.n_outcome_digits() as usize);
+        let mut truncated = oracles[0].attest(&params.event_id, base, n_digits, 2);
+        truncated.digit_signatures.pop();
+        let attestations = [
+            Oracle::random(rng).attest(&params.event_id, base, n_digits, 2),
+            truncated,
+            oracles[0].attest(&params.event_id, base, n_digits, 2),
+            oracles[1].attest(&params.event_id, base, n_digits, 2),
+            oracles[1].attest(&params.event_id, base, n_digits, 2),
+        ];
+        let recovered = bob
+            .receive_oracle_attestation(2, &attestations, &params)
+            .unwrap();
+        assert_eq!(
+            recovered.secret,
+            secrets[2].clone().mark::<(Public, Zero)>()
+        );
+        assert_eq!(
+            recovered.rejected,
+            vec![
+                RejectedShare {
+                    attestation_index: 0,
+                    oracle_index: None,
+                    reason: ShareRejection::UnknownOracle
+                },
+                RejectedShare {
+                    attestation_index: 1,
+                    oracle_index: Some(0),
+                    reason: ShareRejection::WrongSignatureCount {
+                        expected: n_digits,
+                        got: n_digits - 1
+                    }
+                },
+                RejectedShare {
+                    attestation_index: 4,
+                    oracle_index: Some(1),
+                    reason: ShareRejection::DuplicateAttestation
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn bad_attestation_does_not_take_the_oracles_place() {
+        let rng = &mut rand::thread_rng();
+        let oracles = (0..3).map(|_| Oracle::random(rng)).collect::<Vec<_>>();
+        let params = test_params(&oracles, 2);
+        let (secrets, bob) = test_setup(&params, rng).finish(&params, rng);
+
+        let (base, n_digits) = (params.base(), params.n_outcome_digits() as usize);
+        let attestations = [
+            oracles[0].attest(&params.event_id, base, n_digits, 3),
+            oracles[0].attest(&params.event_id, base, n_digits, 2),
+            oracles[1].attest(&params.event_id, base, n_digits, 2),
+        ];
+        let recovered = bob
+            .receive_oracle_attestation(2, &attestations, &params)
+            .unwrap();
+        assert_eq!(
+            recovered.secret,
+            secrets[2].clone().mark::<(Public, Zero)>()
+        );
+        assert_eq!(
+            recovered.rejected,
+            vec![RejectedShare {
+                attestation_index: 0,
+                oracle_index: Some(0),
+                reason: ShareRejection::WrongOutcome { outcome_index: 3 }
+            }]
+        );
+    }
+
+    #[test]
+    fn seeded_run_is_deterministic() {
+        let run = || {
+            let rng = &mut ChaCha20Rng::seed_from_u64(42);
+            let oracles = [Oracle::random(rng)];
+            let params = test_params(&oracles, 1);
+            let setup = test_setup(&params, rng);
+            let message3 = setup
+                .alice
+                .receive_message(setup.message2.clone(), setup.secrets, &params, rng)
+                .unwrap();
+            (
+                setup.message1.encode(),
+                setup.message2.encode(),
+                message3.encode(),
+            )
+        };
+        assert_eq!(run(), run());
+    }
+
+    #[test]
+    fn malformed_message3_is_rejected() {
+        let rng = &mut rand::thread_rng();
+        let oracles = [Oracle::random(rng)];
+        let params = test_params(&oracles, 1);
+        let setup = test_setup(&params, rng);
+        let mut m3 = setup
+            .alice
+            .receive_message(setup.message2, setup.secrets, &params, rng)
+            .unwrap();
+        m3.bit_map_images[0].pop();
+
+        assert_eq!(
+            setup
+                .bob
+                .receive_message(m3, setup.secret_images, &params)
+                .err(),
+            Some(ProtocolError::WrongLength {
+                list: ListKind::OracleBitMapImages { oracle_index: 0 },
+                expected: params.n_outcome_digits() as usize,
+                got: params.n_outcome_digits() as usize - 1,
+            })
+        );
+    }
+
+    #[test]
+    fn bad_opening_is_found() {
+        let rng = &mut rand::thread_rng();
+        let oracles = [Oracle::random(rng)];
+        let params = test_params(&oracles, 1);
+        let setup = test_setup(&params, rng);
+        let bad_commitment_index = *setup.message2.openings.iter().nth(1).unwrap();
+        let mut m3 = setup
+            .alice
+            .receive_message(setup.message2, setup.secrets, &params, rng)
+            .unwrap();
+        m3.openings[1] += bls12_381::Scalar::one();
+
+        assert_eq!(
+            setup
+                .bob
+                .receive_message(m3, setup.secret_images, &params)
+                .err(),
+            Some(ProtocolError::InvalidOpening {
+                commitment_index: bad_commitment_index
+            })
+        );
     }
 }
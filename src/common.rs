@@ -1,45 +1,449 @@
+use crate::{
+    encoding::{read_header, write_header, Reader, Writer},
+    error::ParamsError,
+    fixed_base::{gt_generator_table, GtTable},
+    messages::PARAMS_TAG,
+    oracle::{Announcement, FraudProof, OracleKey},
+    soundness,
+};
 use bls12_381::{
     hash_to_curve::{ExpandMsgXmd, HashToCurve},
     pairing, G1Affine, G2Affine, G2Projective,
 };
-use bls12_381::{multi_miller_loop, G1Projective, G2Prepared, Gt};
-use group::Group;
+use bls12_381::{multi_miller_loop, G1Projective, G2Prepared, Gt, Scalar};
+use core::fmt;
+use ff::Field;
+use rand::{CryptoRng, RngCore};
 use secp256kfun::marker::*;
 use secp256kfun::Scalar as ChainScalar;
+use serde::{Deserialize, Serialize};
 use sha2::{digest::Digest, Sha256};
+use std::sync::{Arc, OnceLock};
 
-#[derive(Clone, Debug)]
+/// The parameters of a contract that Alice and Bob must agree on before running the protocol.
+///
+/// Build it with [`ParamsBuilder`] which makes sure the parameters are consistent.
+#[derive(Clone)]
 pub struct Params {
-    pub oracle_keys: Vec<G1Affine>,
-    pub event_id: String,
-    pub closed_proportion: f64,
-    pub bucket_size: u8,
-    pub n_outcomes: u32,
-    pub threshold: u16,
-    pub elgamal_base: Gt,
-    pub g2_prepared: G2Prepared,
-    pub monotone: bool,
+    pub(crate) oracle_keys: Vec<OracleKey>,
+    pub(crate) event_id: String,
+    pub(crate) closed_proportion: f64,
+    pub(crate) bucket_size: u8,
+    pub(crate) n_outcomes: u32,
+    pub(crate) base: u8,
+    pub(crate) threshold: u16,
+    pub(crate) elgamal_base: Gt,
+    pub(crate) monotone: bool,
+    pub(crate) batch_proofs: bool,
+    /// Computed the first time they're needed and shared between clones.
+    event_context: Arc<OnceLock<EventContext>>,
+    elgamal_base_table: Arc<OnceLock<GtTable>>,
+}
+
+impl fmt::Debug for Params {
+    // the caches are derived from the rest and far too big to print
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Params")
+            .field("oracle_keys", &self.oracle_keys)
+            .field("event_id", &self.event_id)
+            .field("closed_proportion", &self.closed_proportion)
+            .field("bucket_size", &self.bucket_size)
+            .field("n_outcomes", &self.n_outcomes)
+            .field("base", &self.base)
+            .field("threshold", &self.threshold)
+            .field("elgamal_base", &self.elgamal_base)
+            .field("monotone", &self.monotone)
+            .field("batch_proofs", &self.batch_proofs)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Builds [`Params`] from the terms of the contract, checking they make sense and choosing the
+/// cut-and-choose parameters from a security parameter.
+///
+/// ```
+/// # use dlc_venc_pairing::{common::ParamsBuilder, oracle::Oracle};
+/// let oracles = (0..3).map(|_| Oracle::random(&mut rand::thread_rng())).collect::<Vec<_>>();
+/// let params = ParamsBuilder::new(
+///     oracles.iter().map(Oracle::key).collect(),
+///     "btc-usd-2022-06-01",
+///     100,
+///     2,
+/// )
+/// .security_param(30)
+/// .build()
+/// .unwrap();
+/// assert_eq!(params.base(), 2);
+/// assert_eq!(params.n_outcome_digits(), 7);
+/// ```
+#[derive(Clone, Debug)]
+pub struct ParamsBuilder {
+    oracle_keys: Vec<OracleKey>,
+    event_id: String,
+    n_outcomes: u32,
+    threshold: u16,
+    monotone: bool,
+    batch_proofs: bool,
+    base: Option<u8>,
+    security_param: Option<u8>,
+    grinding_bits: Option<u8>,
+    cut_and_choose: Option<(f64, u8)>,
+    elgamal_base: Option<Gt>,
+    cost_weights: CostWeights,
+}
+
+impl ParamsBuilder {
+    pub fn new(
+        oracle_keys: Vec<OracleKey>,
+        event_id: impl Into<String>,
+        n_outcomes: u32,
+        threshold: u16,
+    ) -> Self {
+        Self {
+            oracle_keys,
+            event_id: event_id.into(),
+            n_outcomes,
+            threshold,
+            monotone: false,
+            batch_proofs: false,
+            base: None,
+            security_param: None,
+            grinding_bits: None,
+            cut_and_choose: None,
+            elgamal_base: None,
+            cost_weights: CostWeights::default(),
+        }
+    }
+
+    /// Starts building the contract for the event the oracles announced. Every announcement must
+    /// have a valid signature (the keys themselves are checked by `build`) and they all have to
+    /// agree on the event id, number of outcomes, base and attestation time.
+    pub fn from_announcements(
+        announcements: &[Announcement],
+        threshold: u16,
+    ) -> Result<Self, ParamsError> {
+        let first = announcements.first().ok_or(ParamsError::NoOracles)?;
+        for (oracle_index, announcement) in announcements.iter().enumerate() {
+            if !announcement.verify() {
+                return Err(ParamsError::InvalidAnnouncement { oracle_index });
+            }
+            if announcement.event_id != first.event_id
+                || announcement.n_outcomes != first.n_outcomes
+                || announcement.base != first.base
+                || announcement.attestation_time != first.attestation_time
+            {
+                return Err(ParamsError::AnnouncementMismatch { oracle_index });
+            }
+        }
+        Ok(Self::new(
+            announcements.iter().map(|a| a.key).collect(),
+            first.event_id.clone(),
+            first.n_outcomes,
+            threshold,
+        )
+        .base(first.base))
+    }
+
+    /// Use the payout monotonicity optimization (see the README).
+    pub fn monotone(mut self, monotone: bool) -> Self {
+        self.monotone = monotone;
+        self
+    }
+
+    /// Have Alice send DLEQ proofs that Bob can check all at once (see [`crate::dleq::Proof`]).
+    /// Checking them is much faster but each proof is 305 bytes bigger.
+    pub fn batch_proofs(mut self, batch_proofs: bool) -> Self {
+        self.batch_proofs = batch_proofs;
+        self
+    }
+
+    /// Write outcome indexes in base `base` rather than 2. It has to be the base the oracles
+    /// announced, which [`ParamsBuilder::from_announcements`] sets. Each digit gets `base`
+    /// anticipations (`base - 1` with [`ParamsBuilder::monotone`]).
+    pub fn base(mut self, base: u8) -> Self {
+        self.base = Some(base);
+        self
+    }
+
+    /// Choose the cut-and-choose parameters with [`compute_optimal_params`] so that a cheating
+    /// Alice succeeds with probability at most `2^-security_param`.
+    pub fn security_param(mut self, security_param: u8) -> Self {
+        self.security_param = Some(security_param);
+        self
+    }
+
+    /// Choose the cut-and-choose parameters for the non-interactive mode with
+    /// [`compute_optimal_params_non_interactive`] instead.
+    pub fn non_interactive(mut self, grinding_bits: u8) -> Self {
+        self.grinding_bits = Some(grinding_bits);
+        self
+    }
+
+    /// The costs [`compute_optimal_params`] trades off when choosing the cut-and-choose parameters
+    /// (see [`crate::bench::calibrate`] to measure them).
+    pub fn cost_weights(mut self, cost_weights: CostWeights) -> Self {
+        self.cost_weights = cost_weights;
+        self
+    }
+
+    /// Set the cut-and-choose parameters directly rather than deriving them from a security
+    /// parameter.
+    pub fn cut_and_choose(mut self, closed_proportion: f64, bucket_size: u8) -> Self {
+        self.cut_and_choose = Some((closed_proportion, bucket_size));
+        self
+    }
+
+    /// Use an ElGamal base we were given (e.g. by the counterparty) rather than deriving it.
+    /// `build` fails unless it is the one [`derive_elgamal_base`] gives for this contract.
+    pub fn elgamal_base(mut self, elgamal_base: Gt) -> Self {
+        self.elgamal_base = Some(elgamal_base);
+        self
+    }
+
+    pub fn build(self) -> Result<Params, ParamsError> {
+        let n_oracles = self.oracle_keys.len();
+        if n_oracles == 0 {
+            return Err(ParamsError::NoOracles);
+        }
+        for (oracle_index, key) in self.oracle_keys.iter().enumerate() {
+            if !key.is_valid_public_key() {
+                return Err(ParamsError::InvalidOracleKey { oracle_index });
+            }
+            if !key.verify_proof_of_possession() {
+                return Err(ParamsError::InvalidProofOfPossession { oracle_index });
+            }
+            // a repeated key would get a share for each time it appears
+            if self.oracle_keys[..oracle_index]
+                .iter()
+                .any(|other| other.public_key == key.public_key)
+            {
+                return Err(ParamsError::DuplicateOracleKey { oracle_index });
+            }
+        }
+        if self.threshold == 0 || self.threshold as usize > n_oracles {
+            return Err(ParamsError::InvalidThreshold {
+                threshold: self.threshold,
+                n_oracles,
+            });
+        }
+        if self.n_outcomes == 0 {
+            return Err(ParamsError::NoOutcomes);
+        }
+        if let Some(base) = self.base.filter(|base| *base < 2) {
+            return Err(ParamsError::InvalidBase { base });
+        }
+        self.cost_weights.validate()?;
+
+        let base = self.base.unwrap_or(2);
+        let (closed_proportion, bucket_size) = match (self.cut_and_choose, self.security_param) {
+            (Some(cut_and_choose), _) => cut_and_choose,
+            (None, Some(security_param)) => match self.grinding_bits {
+                Some(grinding_bits) => compute_optimal_params_non_interactive(
+                    security_param,
+                    grinding_bits,
+                    self.n_outcomes,
+                    n_oracles as u32,
+                    base,
+                    self.monotone,
+                    &self.cost_weights,
+                )?,
+                None => compute_optimal_params(
+                    security_param,
+                    self.n_outcomes,
+                    n_oracles as u32,
+                    base,
+                    self.monotone,
+                    &self.cost_weights,
+                )?,
+            },
+            (None, None) => return Err(ParamsError::MissingSecurityParam),
+        };
+
+        // any fewer closed and Alice would have to make an enormous number of commitments
+        let min_closed_proportion = 1.0 / (soundness::MAX_OPENINGS_PER_CLOSED + 1) as f64;
+        if !(closed_proportion >= min_closed_proportion && closed_proportion < 1.0) {
+            return Err(ParamsError::InvalidClosedProportion { closed_proportion });
+        }
+        if bucket_size == 0 {
+            return Err(ParamsError::ZeroBucketSize);
+        }
+
+        let expected_base = derive_elgamal_base(&self.oracle_keys, &self.event_id);
+        if let Some(elgamal_base) = self.elgamal_base {
+            if elgamal_base != expected_base {
+                return Err(ParamsError::ElGamalBaseMismatch);
+            }
+        }
+
+        Ok(Params {
+            oracle_keys: self.oracle_keys,
+            event_id: self.event_id,
+            closed_proportion,
+            bucket_size,
+            n_outcomes: self.n_outcomes,
+            base,
+            threshold: self.threshold,
+            elgamal_base: expected_base,
+            monotone: self.monotone,
+            batch_proofs: self.batch_proofs,
+            event_context: Arc::new(OnceLock::new()),
+            elgamal_base_table: Arc::new(OnceLock::new()),
+        })
+    }
+}
+
+const ELGAMAL_BASE_DST: &[u8] = b"DLC-VENC-PAIRING-V01-ELGAMAL-BASE_XMD:SHA-256_SSWU_RO_";
+
+/// Derives the base of the ElGamal encryption in Gt for a contract with these oracles and event.
+///
+/// It is the pairing of a G1 point hashed from the oracle keys and event id with the G2 generator
+/// so nobody knows its discrete logarithm with respect to the pairing of the generators. Anyone
+/// can recompute it from the terms of the contract to check the base they were given.
+pub fn derive_elgamal_base(oracle_keys: &[OracleKey], event_id: &str) -> Gt {
+    let mut writer = Writer::default();
+    writer.list(oracle_keys, |w, key| w.g1(&key.public_key));
+    writer.str(event_id);
+    let g1 = <G1Projective as HashToCurve<ExpandMsgXmd<Sha256>>>::hash_to_curve(
+        writer.finish(),
+        ELGAMAL_BASE_DST,
+    );
+    pairing(&g1.into(), &G2Affine::generator())
 }
 
 impl Params {
+    pub fn oracle_keys(&self) -> &[OracleKey] {
+        &self.oracle_keys
+    }
+
+    pub fn event_id(&self) -> &str {
+        &self.event_id
+    }
+
+    pub fn closed_proportion(&self) -> f64 {
+        self.closed_proportion
+    }
+
+    pub fn bucket_size(&self) -> u8 {
+        self.bucket_size
+    }
+
+    pub fn n_outcomes(&self) -> u32 {
+        self.n_outcomes
+    }
+
+    /// The base outcome indexes are written in for the oracles to attest to them digit by digit.
+    pub fn base(&self) -> u8 {
+        self.base
+    }
+
+    pub fn threshold(&self) -> u16 {
+        self.threshold
+    }
+
+    pub fn elgamal_base(&self) -> Gt {
+        self.elgamal_base
+    }
+
+    /// Checks the ElGamal base is the one [`derive_elgamal_base`] gives for this contract.
+    pub fn verify_elgamal_base(&self) -> Result<(), ParamsError> {
+        if self.elgamal_base != derive_elgamal_base(&self.oracle_keys, &self.event_id) {
+            return Err(ParamsError::ElGamalBaseMismatch);
+        }
+        Ok(())
+    }
+
+    pub fn monotone(&self) -> bool {
+        self.monotone
+    }
+
+    pub fn batch_proofs(&self) -> bool {
+        self.batch_proofs
+    }
+
+    /// The canonical encoding of the contract terms. Two `Params` encode to the same bytes if and
+    /// only if they describe the same contract.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut writer = Writer::default();
+        write_header(&mut writer, PARAMS_TAG);
+        writer.list(&self.oracle_keys, |w, key| {
+            w.g1(&key.public_key);
+            w.g2(&key.proof_of_possession);
+        });
+        writer.str(&self.event_id);
+        writer.u32(self.n_outcomes);
+        writer.u16(self.threshold);
+        writer.bool(self.monotone);
+        writer.bool(self.batch_proofs);
+        writer.u8(self.base);
+        writer.u64(self.closed_proportion.to_bits());
+        writer.u8(self.bucket_size);
+        writer.gt(&self.elgamal_base);
+        writer.finish()
+    }
+
+    /// Decodes the output of [`Params::encode`], checking the terms the same way [`ParamsBuilder`]
+    /// does. In particular an ElGamal base that wasn't derived from the contract is rejected.
+    pub fn decode(bytes: &[u8]) -> anyhow::Result<Self> {
+        let mut reader = Reader::new(bytes);
+        read_header(&mut reader, PARAMS_TAG)?;
+        let oracle_keys = reader.list(|r| {
+            Ok(OracleKey {
+                public_key: r.g1()?,
+                proof_of_possession: r.g2()?,
+            })
+        })?;
+        let event_id = reader.str()?;
+        let n_outcomes = reader.u32()?;
+        let threshold = reader.u16()?;
+        let monotone = reader.bool()?;
+        let batch_proofs = reader.bool()?;
+        let base = reader.u8()?;
+        let closed_proportion = f64::from_bits(reader.u64()?);
+        let bucket_size = reader.u8()?;
+        let elgamal_base = reader.gt()?;
+        reader.finish()?;
+        Ok(
+            ParamsBuilder::new(oracle_keys, event_id, n_outcomes, threshold)
+                .monotone(monotone)
+                .batch_proofs(batch_proofs)
+                .base(base)
+                .cut_and_choose(closed_proportion, bucket_size)
+                .elgamal_base(elgamal_base)
+                .build()?,
+        )
+    }
+
+    /// A hash of the [canonical encoding] that identifies the contract. It is absorbed into every
+    /// proof transcript so proofs can't be replayed between contracts or events.
+    ///
+    /// [canonical encoding]: Params::encode
+    pub fn fingerprint(&self) -> [u8; 32] {
+        Sha256::default()
+            .chain(b"dlc-venc-pairing/params-fingerprint")
+            .chain(self.encode())
+            .finalize()
+            .into()
+    }
+
+    /// The proof system for this contract (see [`crate::dleq::proof_system`]).
+    pub fn proof_system(&self) -> crate::dleq::ProofSystem {
+        crate::dleq::proof_system(&self.fingerprint())
+    }
+
     pub fn M(&self) -> usize {
         (self.NB() as f64 / self.closed_proportion).ceil() as usize
     }
 
-    pub fn n_outcome_bits(&self) -> u32 {
-        (self.n_outcomes as f32).log2().ceil() as u32
+    pub fn n_outcome_digits(&self) -> u32 {
+        n_outcome_digits(self.n_outcomes, self.base)
     }
 
     pub fn n_anticipations_per_oracle(&self) -> u32 {
-        self.n_outcome_bits() * self.n_anticipations_per_bit()
+        self.n_outcome_digits() * self.n_anticipations_per_digit()
     }
 
-    pub fn n_anticipations_per_bit(&self) -> u32 {
-        if self.monotone {
-            1
-        } else {
-            2
-        }
+    pub fn n_anticipations_per_digit(&self) -> u32 {
+        n_anticipations_per_digit(self.base, self.monotone)
     }
 
     pub fn NB(&self) -> usize {
@@ -52,47 +456,213 @@ impl Params {
         self.M() - self.NB()
     }
 
-    pub fn iter_anticipations(&self, oracle_index: usize) -> impl Iterator<Item = [Gt; 2]> + '_ {
-        (0..self.n_outcome_bits()).map(move |bit| {
-            [
-                self.anticipate_at_index(oracle_index, bit, false),
-                self.anticipate_at_index(oracle_index, bit, true),
-            ]
+    /// The bits of soundness these cut-and-choose parameters actually give against a cheating
+    /// Alice in the interactive mode (see [`soundness::soundness_bits`]). In the non-interactive
+    /// mode subtract the grinding bits.
+    pub fn soundness_bits(&self) -> f64 {
+        let n_buckets = self.NB() / self.bucket_size as usize;
+        soundness::soundness_bits(
+            self.M(),
+            n_buckets,
+            self.bucket_size as usize,
+            self.num_openings(),
+        )
+    }
+
+    /// The message hashes and anticipated attestations for this contract's event. They're computed
+    /// on the first call which takes a pairing for each oracle, digit and digit value.
+    pub fn event_context(&self) -> &EventContext {
+        self.event_context.get_or_init(|| {
+            EventContext::new(
+                &self.oracle_keys,
+                &self.event_id,
+                self.base,
+                self.n_outcome_digits(),
+            )
         })
     }
 
+    /// A fixed-base table for the ElGamal base, sized for Alice's `M` commitments.
+    pub(crate) fn elgamal_base_table(&self) -> &GtTable {
+        self.elgamal_base_table
+            .get_or_init(|| GtTable::new(self.elgamal_base, self.M()))
+    }
+
+    pub fn iter_anticipations(&self, oracle_index: usize) -> impl Iterator<Item = Vec<Gt>> + '_ {
+        self.event_context()
+            .anticipations(oracle_index)
+            .iter()
+            .cloned()
+    }
+
     pub fn anticipate_at_index(
         &self,
         oracle_index: usize,
-        outcome_bit_index: u32,
-        outcome_bit_value: bool,
+        outcome_digit_index: u32,
+        outcome_digit_value: u32,
     ) -> Gt {
-        let message = message_for_event_index(&self.event_id, outcome_bit_index, outcome_bit_value);
-        pairing(&self.oracle_keys[oracle_index as usize], &message)
+        self.event_context()
+            .anticipation(oracle_index, outcome_digit_index, outcome_digit_value)
     }
 
     pub fn verify_bls_sig(
         &self,
         oracle_index: usize,
-        outcome_bit_index: u32,
-        outcome_bit_value: bool,
+        outcome_digit_index: u32,
+        outcome_digit_value: u32,
         sig: G2Affine,
     ) -> bool {
-        let gt = pairing(&G1Affine::generator(), &sig);
-        let expected = self.anticipate_at_index(oracle_index, outcome_bit_index, outcome_bit_value);
-        gt == expected
+        self.event_context().verify_bls_sig(
+            &self.oracle_keys[oracle_index].public_key,
+            outcome_digit_index,
+            outcome_digit_value,
+            &sig,
+        )
     }
 
-    pub fn map_Zq_to_Gt(&self, ri: &ChainScalar) -> (Gt, [u8; 32]) {
-        let gt_elem = {
-            let g1 = G1Affine::from(G1Projective::random(&mut rand::thread_rng()));
-            multi_miller_loop(&[(&g1, &self.g2_prepared)]).final_exponentiation()
-        };
+    /// Checks whether the two `(digit value, signature)` pairs show the oracle signed two different
+    /// values of a digit of the event, returning the proof if so.
+    pub fn detect_equivocation(
+        &self,
+        oracle_index: usize,
+        outcome_digit_index: u32,
+        (first_value, first_sig): (u32, G2Affine),
+        (second_value, second_sig): (u32, G2Affine),
+    ) -> Option<FraudProof> {
+        if first_value == second_value
+            || !self.verify_bls_sig(oracle_index, outcome_digit_index, first_value, first_sig)
+            || !self.verify_bls_sig(oracle_index, outcome_digit_index, second_value, second_sig)
+        {
+            return None;
+        }
+        Some(FraudProof {
+            public_key: self.oracle_keys[oracle_index].public_key,
+            event_id: self.event_id.clone(),
+            base: self.base,
+            digit_index: outcome_digit_index,
+            first_value,
+            first_signature: first_sig,
+            second_value,
+            second_signature: second_sig,
+        })
+    }
+
+    pub fn map_Zq_to_Gt(
+        &self,
+        ri: &ChainScalar,
+        rng: &mut (impl RngCore + CryptoRng),
+    ) -> (Gt, [u8; 32]) {
+        let gt_elem = gt_generator_table().mul(&Scalar::random(rng));
         let mut hashed_xor_ri = Sha256::default().chain(gt_elem.to_compressed()).finalize();
         for (xor_byte, ri_byte) in hashed_xor_ri.iter_mut().zip(ri.to_bytes()) {
             *xor_byte ^= ri_byte
         }
-        (gt_elem, hashed_xor_ri.try_into().unwrap())
+        (gt_elem, hashed_xor_ri.into())
+    }
+}
+
+/// Everything about an event that can be computed before the oracles attest: the message each
+/// digit value is signed under and what each oracle's signature on it will pair to.
+///
+/// Computing these takes a hash to G2 per digit value and a pairing per oracle and digit value,
+/// which both Alice and Bob need and Bob needs again when the attestations come in, so they're
+/// worked out once here. [`Params::event_context`] caches one for the contract's event.
+#[derive(Clone, Debug)]
+pub struct EventContext {
+    event_id: String,
+    base: u8,
+    messages: Vec<Vec<G2Affine>>,
+    prepared_messages: Vec<Vec<G2Prepared>>,
+    anticipations: Vec<Vec<Vec<Gt>>>,
+}
+
+impl EventContext {
+    pub fn new(oracle_keys: &[OracleKey], event_id: &str, base: u8, n_outcome_digits: u32) -> Self {
+        let messages = crate::parallel::map((0..n_outcome_digits).collect(), |digit_index| {
+            (0..base as u32)
+                .map(|digit_value| {
+                    message_for_event_index(event_id, base, digit_index, digit_value)
+                })
+                .collect::<Vec<_>>()
+        });
+        let prepared_messages = messages
+            .iter()
+            .map(|messages| messages.iter().copied().map(G2Prepared::from).collect())
+            .collect::<Vec<Vec<_>>>();
+        let anticipations = crate::parallel::map(oracle_keys.iter().collect(), |key| {
+            prepared_messages
+                .iter()
+                .map(|prepared| {
+                    prepared
+                        .iter()
+                        .map(|prepared| {
+                            multi_miller_loop(&[(&key.public_key, prepared)]).final_exponentiation()
+                        })
+                        .collect()
+                })
+                .collect()
+        });
+        EventContext {
+            event_id: event_id.to_string(),
+            base,
+            messages,
+            prepared_messages,
+            anticipations,
+        }
+    }
+
+    pub fn event_id(&self) -> &str {
+        &self.event_id
+    }
+
+    pub fn base(&self) -> u8 {
+        self.base
+    }
+
+    pub fn n_outcome_digits(&self) -> u32 {
+        self.messages.len() as u32
+    }
+
+    /// The same as [`message_for_event_index`].
+    pub fn message(&self, outcome_digit_index: u32, outcome_digit_value: u32) -> G2Affine {
+        self.messages[outcome_digit_index as usize][outcome_digit_value as usize]
+    }
+
+    /// The anticipated attestation of each value of each digit of the outcome by the
+    /// `oracle_index`th oracle.
+    pub fn anticipations(&self, oracle_index: usize) -> &[Vec<Gt>] {
+        &self.anticipations[oracle_index]
+    }
+
+    pub fn anticipation(
+        &self,
+        oracle_index: usize,
+        outcome_digit_index: u32,
+        outcome_digit_value: u32,
+    ) -> Gt {
+        self.anticipations[oracle_index][outcome_digit_index as usize][outcome_digit_value as usize]
+    }
+
+    /// The same as [`verify_bls_sig`] for this event but without hashing the message.
+    pub fn verify_bls_sig(
+        &self,
+        public_key: &G1Affine,
+        outcome_digit_index: u32,
+        outcome_digit_value: u32,
+        sig: &G2Affine,
+    ) -> bool {
+        if !bool::from(sig.is_on_curve() & sig.is_torsion_free()) {
+            return false;
+        }
+        multi_miller_loop(&[
+            (
+                public_key,
+                &self.prepared_messages[outcome_digit_index as usize][outcome_digit_value as usize],
+            ),
+            (&-G1Affine::generator(), &G2Prepared::from(*sig)),
+        ])
+        .final_exponentiation()
+            == Gt::identity()
     }
 }
 
@@ -103,85 +673,211 @@ pub fn map_Gt_to_Zq(ri_mapped: &Gt, pad: [u8; 32]) -> ChainScalar<Secret, Zero>
     for (xor_byte, pad_byte) in ri_bytes.iter_mut().zip(pad) {
         *xor_byte ^= pad_byte
     }
-    ChainScalar::from_bytes_mod_order(ri_bytes.try_into().unwrap())
+    ChainScalar::from_bytes_mod_order(ri_bytes.into())
 }
 
+/// The message an oracle signs to attest that digit `outcome_digit_index` of the outcome of
+/// `event_id` written in base `base` is `outcome_digit_value`. The base is part of the message so
+/// signatures for one base can't be used to decrypt a contract in another.
 pub fn message_for_event_index(
     event_id: &str,
-    outcome_bit_index: u32,
-    outcome_bit_value: bool,
+    base: u8,
+    outcome_digit_index: u32,
+    outcome_digit_value: u32,
 ) -> G2Affine {
     <G2Projective as HashToCurve<ExpandMsgXmd<Sha256>>>::hash_to_curve(
-        format!("{}/{}/{}", event_id, outcome_bit_index, outcome_bit_value),
+        format!(
+            "{}/{}/{}/{}",
+            event_id, base, outcome_digit_index, outcome_digit_value
+        ),
         b"dlc-message",
     )
     .into()
 }
 
-fn cost_function(n_total: usize, n_open: usize) -> u64 {
-    let n_closed = n_total - n_open;
-    // rough measurements in ms of how long processing each stage of each commit takes.
-    // TODO: do an experiment to find the right numbers in practice!
-    let weight_open: f64 = 2.0;
-    let weight_closed: f64 = 7.35;
-    let weight_any: f64 = 3.29;
+/// Checks `sig` is `public_key`'s signature on the value of a digit of the outcome of `event_id`.
+pub fn verify_bls_sig(
+    public_key: &G1Affine,
+    event_id: &str,
+    base: u8,
+    outcome_digit_index: u32,
+    outcome_digit_value: u32,
+    sig: &G2Affine,
+) -> bool {
+    if !bool::from(sig.is_on_curve() & sig.is_torsion_free()) {
+        return false;
+    }
+    let message = message_for_event_index(event_id, base, outcome_digit_index, outcome_digit_value);
+    multi_miller_loop(&[
+        (public_key, &G2Prepared::from(message)),
+        (&-G1Affine::generator(), &G2Prepared::from(*sig)),
+    ])
+    .final_exponentiation()
+        == Gt::identity()
+}
 
-    (weight_any * n_total as f64 + n_closed as f64 * weight_closed + n_open as f64 * weight_open)
-        .ceil() as u64
+/// How long each part of the cut-and-choose takes per commitment, in milliseconds.
+/// [`compute_optimal_params`] uses them to pick the cheapest parameters for a security level.
+///
+/// The default is a rough measurement from one machine. Use [`crate::bench::calibrate`] (or the
+/// `calibrate` subcommand of the `run` binary) to measure them on yours.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct CostWeights {
+    /// Making a commitment, sending it and checking it decodes. Every commitment pays this.
+    pub any: f64,
+    /// Checking an opened commitment.
+    pub open: f64,
+    /// Encrypting under an anticipated attestation, proving and verifying it for a closed
+    /// commitment.
+    pub closed: f64,
 }
 
+impl Default for CostWeights {
+    fn default() -> Self {
+        CostWeights {
+            any: 3.29,
+            open: 2.0,
+            closed: 7.35,
+        }
+    }
+}
+
+impl CostWeights {
+    /// Checks every weight is positive and finite. [`ParamsBuilder::build`] does this too.
+    pub fn validate(&self) -> Result<(), ParamsError> {
+        for (weight, value) in [
+            ("any", self.any),
+            ("open", self.open),
+            ("closed", self.closed),
+        ] {
+            if !(value.is_finite() && value > 0.0) {
+                return Err(ParamsError::InvalidCostWeight { weight, value });
+            }
+        }
+        Ok(())
+    }
+
+    pub(crate) fn cost(&self, n_total: usize, n_open: usize) -> u64 {
+        let n_closed = n_total - n_open;
+        (self.any * n_total as f64 + n_closed as f64 * self.closed + n_open as f64 * self.open)
+            .ceil() as u64
+    }
+}
+
+/// Picks the closed proportion and bucket size that cost the least according to `weights` while
+/// giving at least `security_param` bits of soundness (see [`crate::soundness`]).
 pub fn compute_optimal_params(
     security_param: u8,
     n_outcomes: u32,
     n_oracles: u32,
+    base: u8,
     monotone: bool,
-) -> (f64, u8) {
-    if n_outcomes * n_oracles == 1 {
-        // this is cheating and not quite right but better than panicing on this edge case
-        return (0.5, security_param);
-    }
-    let n_outcomes = n_outcomes as f64;
-    let mut n_encryptions = n_outcomes.log2().ceil() * if monotone { 1.0 } else { 2.0 };
-    if n_encryptions == 0.0 {
-        n_encryptions = 1.0;
-    }
-    let n_oracles = n_oracles as f64;
-    let N = n_encryptions * n_oracles;
-    let s = security_param as f64;
-
-    // go through each possible proportion closed to see which gives the best score
-    let (B, p, _) = (500..999)
-        .filter_map(|p| {
-            let p = (p as f64) / 1000.0;
-            // requirement for the formula below to hold
-            if N < (1.0 / (1.0 - p)) {
-                return None;
-            }
-            let B = ((s as f64 + (N as f64).log2() - p.log2())
-                / ((N - N * p).log2() - p.log2() / (1.0 - p)))
-                .ceil();
-            let total_closed = B * N;
-            let total = total_closed / p;
-            let total_opened = total - total_closed;
-
-            Some((
-                B as u8,
-                p,
-                cost_function(total as usize, total_opened as usize),
-            ))
+    weights: &CostWeights,
+) -> Result<(f64, u8), ParamsError> {
+    let N = n_buckets(n_outcomes, n_oracles, base, monotone);
+    let chosen = soundness::optimal_cut_and_choose(security_param, N, weights)
+        .ok_or(ParamsError::UnreachableSecurityParam { security_param })?;
+    let NB = (N * chosen.bucket_size as usize) as f64;
+    let M = chosen.M(N);
+    // Params::M computes ceil(NB / p) so make sure rounding doesn't give us an extra commitment
+    let mut p = NB / M as f64;
+    while (NB / p).ceil() as usize > M {
+        p = p.next_up();
+    }
+    Ok((p, chosen.bucket_size))
+}
+
+/// Suggests a base for an oracle to announce: the one whose cut-and-choose costs the least
+/// according to `weights` for contracts like the one described. Bigger bases mean fewer digits but
+/// more anticipations per digit. When two bases cost the same the smaller one wins since it has
+/// fewer anticipations to compute.
+pub fn compute_optimal_base(
+    security_param: u8,
+    n_outcomes: u32,
+    n_oracles: u32,
+    monotone: bool,
+    weights: &CostWeights,
+) -> Result<u8, ParamsError> {
+    // a base bigger than the number of outcomes only adds anticipations
+    let max_base = n_outcomes.clamp(2, MAX_OPTIMAL_BASE as u32) as u8;
+    (2..=max_base)
+        .filter_map(|base| {
+            let N = n_buckets(n_outcomes, n_oracles, base, monotone);
+            let chosen = soundness::optimal_cut_and_choose(security_param, N, weights)?;
+            Some((weights.cost(chosen.M(N), chosen.n_openings), base))
         })
-        .min_by_key(|(_, _, score)| *score)
-        .unwrap();
+        .min()
+        .map(|(_, base)| base)
+        .ok_or(ParamsError::UnreachableSecurityParam { security_param })
+}
+
+/// The largest base [`compute_optimal_base`] considers.
+const MAX_OPTIMAL_BASE: u8 = 16;
 
-    (p, B)
+/// How many buckets the cut-and-choose has to fill: one for each oracle and anticipation.
+fn n_buckets(n_outcomes: u32, n_oracles: u32, base: u8, monotone: bool) -> usize {
+    n_outcome_digits(n_outcomes, base) as usize
+        * n_anticipations_per_digit(base, monotone) as usize
+        * n_oracles.max(1) as usize
+}
+
+/// Like [`compute_optimal_params`] but for the non-interactive mode where `Message2` is derived by
+/// hashing `Message1`.
+///
+/// There Alice can keep generating fresh commitments until the hash gives her a challenge that
+/// lets her cheat so each attempt succeeds with the interactive soundness error. If we assume
+/// she can afford `2^grinding_bits` attempts, the cut-and-choose has to give `security_param +
+/// grinding_bits` bits for her overall chance to stay below `2^-security_param`.
+pub fn compute_optimal_params_non_interactive(
+    security_param: u8,
+    grinding_bits: u8,
+    n_outcomes: u32,
+    n_oracles: u32,
+    base: u8,
+    monotone: bool,
+    weights: &CostWeights,
+) -> Result<(f64, u8), ParamsError> {
+    compute_optimal_params(
+        security_param.saturating_add(grinding_bits),
+        n_outcomes,
+        n_oracles,
+        base,
+        monotone,
+        weights,
+    )
 }
 
-pub fn to_bits(mut num: u32, bit_length: usize) -> Vec<bool> {
-    (0..bit_length)
+/// How many base-`base` digits it takes to write every index in `0..n_outcomes`.
+pub fn n_outcome_digits(n_outcomes: u32, base: u8) -> u32 {
+    // even a single outcome gets a digit so there is something to attest to
+    let mut n_digits = 1;
+    let mut n_representable = base as u64;
+    while n_representable < n_outcomes as u64 {
+        n_representable *= base as u64;
+        n_digits += 1;
+    }
+    n_digits
+}
+
+/// In the monotone mode the highest value of each digit doesn't add to the outcome's pad so it
+/// doesn't need an anticipation. That way Bob can only ever decrypt the secrets of outcomes whose
+/// digits are each either the same as the attested one or the highest value, which are all greater
+/// than or equal to it.
+pub fn n_anticipations_per_digit(base: u8, monotone: bool) -> u32 {
+    if monotone {
+        base as u32 - 1
+    } else {
+        base as u32
+    }
+}
+
+/// The `n_digits` base-`base` digits of `num`, least significant first.
+pub fn to_digits(mut num: u32, base: u8, n_digits: usize) -> Vec<u32> {
+    (0..n_digits)
         .map(|_| {
-            let bit = num & 0x01 == 1;
-            num = num >> 1;
-            bit
+            let digit = num % base as u32;
+            num /= base as u32;
+            digit
         })
         .collect()
 }
@@ -189,9 +885,237 @@ pub fn to_bits(mut num: u32, bit_length: usize) -> Vec<bool> {
 #[cfg(test)]
 mod test {
     use super::*;
+
+    #[test]
+    fn params_builder_rejects_inconsistent_params() {
+        let oracle_keys = (0..3)
+            .map(|_| crate::oracle::Oracle::random(&mut rand::thread_rng()).key())
+            .collect::<Vec<_>>();
+        let builder = |n_outcomes, threshold| {
+            ParamsBuilder::new(oracle_keys.clone(), "test", n_outcomes, threshold)
+                .security_param(30)
+        };
+        assert!(builder(10, 2).build().is_ok());
+        assert_eq!(
+            builder(10, 4).build().unwrap_err(),
+            ParamsError::InvalidThreshold {
+                threshold: 4,
+                n_oracles: 3
+            }
+        );
+        assert!(matches!(
+            builder(10, 0).build(),
+            Err(ParamsError::InvalidThreshold { .. })
+        ));
+        assert_eq!(builder(0, 2).build().unwrap_err(), ParamsError::NoOutcomes);
+        assert_eq!(
+            ParamsBuilder::new(vec![], "test", 10, 1)
+                .security_param(30)
+                .build()
+                .unwrap_err(),
+            ParamsError::NoOracles
+        );
+        assert_eq!(
+            builder(10, 2).cut_and_choose(0.5, 0).build().unwrap_err(),
+            ParamsError::ZeroBucketSize
+        );
+        for closed_proportion in [1.0, 1e-9] {
+            assert!(matches!(
+                builder(10, 2).cut_and_choose(closed_proportion, 3).build(),
+                Err(ParamsError::InvalidClosedProportion { .. })
+            ));
+        }
+        assert_eq!(
+            ParamsBuilder::new(oracle_keys.clone(), "test", 10, 1)
+                .build()
+                .unwrap_err(),
+            ParamsError::MissingSecurityParam
+        );
+        for value in [0.0, -1.0, f64::NAN, f64::INFINITY] {
+            let weights = CostWeights {
+                open: value,
+                ..CostWeights::default()
+            };
+            assert!(matches!(
+                builder(10, 2).cost_weights(weights).build(),
+                Err(ParamsError::InvalidCostWeight { weight: "open", .. })
+            ));
+        }
+
+        let mut bad_pop = oracle_keys.clone();
+        bad_pop[1].proof_of_possession = bad_pop[0].proof_of_possession;
+        assert_eq!(
+            ParamsBuilder::new(bad_pop, "test", 10, 1)
+                .security_param(30)
+                .build()
+                .unwrap_err(),
+            ParamsError::InvalidProofOfPossession { oracle_index: 1 }
+        );
+        let mut duplicate = oracle_keys.clone();
+        duplicate[2] = duplicate[0];
+        assert_eq!(
+            ParamsBuilder::new(duplicate, "test", 10, 1)
+                .security_param(30)
+                .build()
+                .unwrap_err(),
+            ParamsError::DuplicateOracleKey { oracle_index: 2 }
+        );
+        let mut identity_key = oracle_keys;
+        identity_key[2] = OracleKey {
+            public_key: G1Affine::identity(),
+            proof_of_possession: G2Affine::identity(),
+        };
+        assert_eq!(
+            ParamsBuilder::new(identity_key, "test", 10, 1)
+                .security_param(30)
+                .build()
+                .unwrap_err(),
+            ParamsError::InvalidOracleKey { oracle_index: 2 }
+        );
+    }
+    #[test]
+    fn params_encoding_roundtrip() {
+        let oracle_keys = (0..3)
+            .map(|_| crate::oracle::Oracle::random(&mut rand::thread_rng()).key())
+            .collect::<Vec<_>>();
+        let params = ParamsBuilder::new(oracle_keys.clone(), "event-a", 10, 2)
+            .monotone(true)
+            .security_param(30)
+            .build()
+            .unwrap();
+        let decoded = Params::decode(&params.encode()).unwrap();
+        assert_eq!(decoded.encode(), params.encode());
+        assert_eq!(decoded.fingerprint(), params.fingerprint());
+        assert!(params.soundness_bits() >= 30.0);
+        assert_eq!(decoded.soundness_bits(), params.soundness_bits());
+
+        let other_event = ParamsBuilder::new(oracle_keys, "event-b", 10, 2)
+            .monotone(true)
+            .security_param(30)
+            .build()
+            .unwrap();
+        assert_ne!(other_event.fingerprint(), params.fingerprint());
+        assert_ne!(other_event.elgamal_base(), params.elgamal_base());
+        assert_eq!(params.verify_elgamal_base(), Ok(()));
+
+        let mut wrong_base = params.encode();
+        let base_start = wrong_base.len() - 288;
+        wrong_base[base_start..].copy_from_slice(&other_event.elgamal_base().to_compressed());
+        assert!(Params::decode(&wrong_base).is_err());
+
+        let mut tiny_proportion = params.clone();
+        tiny_proportion.closed_proportion = 1e-9;
+        assert!(Params::decode(&tiny_proportion.encode()).is_err());
+    }
+
+    #[test]
+    fn params_from_announcements() {
+        use crate::oracle::Oracle;
+        let oracles = (0..3)
+            .map(|_| Oracle::random(&mut rand::thread_rng()))
+            .collect::<Vec<_>>();
+        let mut announcements = oracles
+            .iter()
+            .map(|oracle| oracle.announce("event", 10, 3, 1_000))
+            .collect::<Vec<_>>();
+        let params = ParamsBuilder::from_announcements(&announcements, 2)
+            .unwrap()
+            .security_param(30)
+            .build()
+            .unwrap();
+        assert_eq!(params.event_id(), "event");
+        assert_eq!(params.n_outcomes(), 10);
+        assert_eq!(params.base(), 3);
+
+        announcements[2] = oracles[2].announce("event", 10, 2, 1_000);
+        assert_eq!(
+            ParamsBuilder::from_announcements(&announcements, 2).unwrap_err(),
+            ParamsError::AnnouncementMismatch { oracle_index: 2 }
+        );
+        announcements[2] = oracles[2].announce("event", 11, 3, 1_000);
+        assert_eq!(
+            ParamsBuilder::from_announcements(&announcements, 2).unwrap_err(),
+            ParamsError::AnnouncementMismatch { oracle_index: 2 }
+        );
+        announcements[2].n_outcomes = 10;
+        assert_eq!(
+            ParamsBuilder::from_announcements(&announcements, 2).unwrap_err(),
+            ParamsError::InvalidAnnouncement { oracle_index: 2 }
+        );
+    }
+
+    #[test]
+    fn event_context_matches_direct_computation() {
+        use crate::oracle::Oracle;
+        let oracles = (0..2)
+            .map(|_| Oracle::random(&mut rand::thread_rng()))
+            .collect::<Vec<_>>();
+        let params = ParamsBuilder::new(oracles.iter().map(Oracle::key).collect(), "event", 4, 1)
+            .security_param(30)
+            .build()
+            .unwrap();
+        let context = params.event_context();
+        let base = params.base();
+        for (oracle_index, oracle) in oracles.iter().enumerate() {
+            for digit_index in 0..params.n_outcome_digits() {
+                for digit_value in 0..base as u32 {
+                    let message = message_for_event_index("event", base, digit_index, digit_value);
+                    assert_eq!(context.message(digit_index, digit_value), message);
+                    assert_eq!(
+                        params.anticipate_at_index(oracle_index, digit_index, digit_value),
+                        pairing(&oracle.public_key(), &message)
+                    );
+                }
+            }
+        }
+
+        let n_digits = params.n_outcome_digits() as usize;
+        let attestation = oracles[1].attest("event", base, n_digits, 3);
+        let sig = attestation.digit_signatures[0];
+        assert!(attestation.verify_with_context(context));
+        assert!(params.verify_bls_sig(1, 0, 3 % base as u32, sig));
+        assert!(!params.verify_bls_sig(1, 0, 2 % base as u32, sig));
+        let other_event = oracles[1].attest("other-event", base, n_digits, 3);
+        assert!(!other_event.verify_with_context(context));
+        let other_base = oracles[1].attest("event", base + 1, n_digits, 3);
+        assert!(!other_base.verify_with_context(context));
+        // the high digits of outcome 1 are zero so dropping them leaves a valid attestation to 1
+        let mut truncated = oracles[1].attest("event", base, n_digits, 1);
+        truncated.digit_signatures.truncate(1);
+        assert!(truncated.verify());
+        assert!(!truncated.verify_with_context(context));
+    }
+
+    #[test]
+    fn cost_weights_steer_the_cut_and_choose() {
+        let cheap_openings = CostWeights {
+            open: 0.01,
+            ..CostWeights::default()
+        };
+        let expensive_openings = CostWeights {
+            open: 100.0,
+            ..CostWeights::default()
+        };
+        let (cheap_p, cheap_b) =
+            compute_optimal_params(30, 100, 3, 2, false, &cheap_openings).unwrap();
+        let (expensive_p, expensive_b) =
+            compute_optimal_params(30, 100, 3, 2, false, &expensive_openings).unwrap();
+        assert!(cheap_p < expensive_p);
+        assert!(cheap_b <= expensive_b);
+    }
+
     #[test]
-    fn test_to_bits() {
-        assert_eq!(to_bits(0x01, 2), vec![true, false]);
-        assert_eq!(to_bits(0x3, 2), vec![true, true]);
+    fn test_to_digits() {
+        assert_eq!(to_digits(0x01, 2, 2), vec![1, 0]);
+        assert_eq!(to_digits(0x3, 2, 2), vec![1, 1]);
+        assert_eq!(to_digits(23, 3, 3), vec![2, 1, 2]);
+        assert_eq!(n_outcome_digits(1, 2), 1);
+        assert_eq!(n_outcome_digits(9, 3), 2);
+        assert_eq!(n_outcome_digits(10, 3), 3);
+        // 9 outcomes take 4 bits (8 buckets per oracle) but only 2 trits (6 buckets)
+        assert_eq!(
+            compute_optimal_base(30, 9, 1, false, &CostWeights::default()),
+            Ok(3)
+        );
     }
 }
@@ -0,0 +1,780 @@
+//! Gt exponentiation with cyclotomic squaring.
+//!
+//! Elements of Gt live in the cyclotomic subgroup of Fp12 where squaring can be done with about
+//! half the work of a generic Fp12 squaring ([Granger–Scott]). The curve library only uses this
+//! inside the final exponentiation and keeps its field types private so this module carries its own
+//! copy of the Fp, Fp2, Fp6 and Fp12 arithmetic, ported from the curve library.
+//!
+//! There's no cheap way to turn the result back into a [`Gt`] (the library checks that
+//! deserialized elements are in the subgroup which costs an exponentiation) so results stay as
+//! [`Fp12`] and can only be multiplied, compared or serialized.
+//!
+//! [Granger–Scott]: https://eprint.iacr.org/2009/565
+use bls12_381::{Gt, Scalar};
+use subtle::{Choice, ConditionallySelectable, ConstantTimeEq};
+
+/// p = 4002409555221667393417789825735904156556882819939007885332058136124031650490837864442687629129015664037894272559787
+const MODULUS: [u64; 6] = [
+    0xb9fe_ffff_ffff_aaab,
+    0x1eab_fffe_b153_ffff,
+    0x6730_d2a0_f6b0_f624,
+    0x6477_4b84_f385_12bf,
+    0x4b1b_a7b6_434b_acd7,
+    0x1a01_11ea_397f_e69a,
+];
+
+/// INV = -(p^{-1} mod 2^64) mod 2^64
+const INV: u64 = 0x89f3_fffc_fffc_fffd;
+
+/// R = 2^384 mod p
+const R: Fp = Fp([
+    0x7609_0000_0002_fffd,
+    0xebf4_000b_c40c_0002,
+    0x5f48_9857_53c7_58ba,
+    0x77ce_5853_7052_5745,
+    0x5c07_1a97_a256_ec6d,
+    0x15f6_5ec3_fa80_e493,
+]);
+
+/// R2 = 2^(384*2) mod p
+const R2: Fp = Fp([
+    0xf4df_1f34_1c34_1746,
+    0x0a76_e6a6_09d1_04f1,
+    0x8de5_476c_4c95_b6d5,
+    0x67eb_88a9_939d_83c0,
+    0x9a79_3e85_b519_952d,
+    0x1198_8fe5_92ca_e3aa,
+]);
+
+/// (p - 1) / 2 + 1
+const HALF_MODULUS_PLUS_ONE: [u64; 6] = [
+    0xdcff_7fff_ffff_d556,
+    0x0f55_ffff_58a9_ffff,
+    0xb398_6950_7b58_7b12,
+    0xb23b_a5c2_79c2_895f,
+    0x258d_d3db_21a5_d66b,
+    0x0d00_88f5_1cbf_f34d,
+];
+
+/// The width of the exponent windows in [`Fp12::pow`].
+const WINDOW_WIDTH: usize = 4;
+
+#[inline(always)]
+fn adc(a: u64, b: u64, carry: u64) -> (u64, u64) {
+    let ret = (a as u128) + (b as u128) + (carry as u128);
+    (ret as u64, (ret >> 64) as u64)
+}
+
+#[inline(always)]
+fn sbb(a: u64, b: u64, borrow: u64) -> (u64, u64) {
+    let ret = (a as u128).wrapping_sub((b as u128) + ((borrow >> 63) as u128));
+    (ret as u64, (ret >> 64) as u64)
+}
+
+#[inline(always)]
+fn mac(a: u64, b: u64, c: u64, carry: u64) -> (u64, u64) {
+    let ret = (a as u128) + ((b as u128) * (c as u128)) + (carry as u128);
+    (ret as u64, (ret >> 64) as u64)
+}
+
+/// An element of Fp in Montgomery form.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Fp([u64; 6]);
+
+impl ConditionallySelectable for Fp {
+    fn conditional_select(a: &Self, b: &Self, choice: Choice) -> Self {
+        let mut limbs = [0; 6];
+        for (i, limb) in limbs.iter_mut().enumerate() {
+            *limb = u64::conditional_select(&a.0[i], &b.0[i], choice);
+        }
+        Fp(limbs)
+    }
+}
+
+impl Fp {
+    const ZERO: Fp = Fp([0; 6]);
+
+    /// The bytes must be canonical which they always are when they come from a [`Gt`].
+    fn from_bytes(bytes: &[u8; 48]) -> Fp {
+        let mut limbs = [0; 6];
+        for (i, limb) in limbs.iter_mut().enumerate() {
+            *limb = u64::from_be_bytes(bytes[40 - 8 * i..48 - 8 * i].try_into().unwrap());
+        }
+        Fp(limbs).mul(&R2)
+    }
+
+    fn to_bytes(self) -> [u8; 48] {
+        let canonical = self.canonical();
+        let mut bytes = [0; 48];
+        for (i, limb) in canonical.iter().enumerate() {
+            bytes[40 - 8 * i..48 - 8 * i].copy_from_slice(&limb.to_be_bytes());
+        }
+        bytes
+    }
+
+    fn canonical(&self) -> [u64; 6] {
+        let s = &self.0;
+        Fp::montgomery_reduce(s[0], s[1], s[2], s[3], s[4], s[5], 0, 0, 0, 0, 0, 0).0
+    }
+
+    fn is_zero(&self) -> bool {
+        self.0 == [0; 6]
+    }
+
+    /// Whether this is bigger than its negation.
+    fn lexicographically_largest(&self) -> bool {
+        let canonical = self.canonical();
+        let mut borrow = 0;
+        for (limb, half) in canonical.iter().zip(HALF_MODULUS_PLUS_ONE) {
+            borrow = sbb(*limb, half, borrow).1;
+        }
+        borrow == 0
+    }
+
+    #[inline]
+    fn subtract_p(&self) -> Fp {
+        let (r0, borrow) = sbb(self.0[0], MODULUS[0], 0);
+        let (r1, borrow) = sbb(self.0[1], MODULUS[1], borrow);
+        let (r2, borrow) = sbb(self.0[2], MODULUS[2], borrow);
+        let (r3, borrow) = sbb(self.0[3], MODULUS[3], borrow);
+        let (r4, borrow) = sbb(self.0[4], MODULUS[4], borrow);
+        let (r5, borrow) = sbb(self.0[5], MODULUS[5], borrow);
+
+        // If underflow occurred on the final limb, borrow = 0xfff...fff, otherwise
+        // borrow = 0x000...000. Thus, we use it as a mask!
+        let r0 = (self.0[0] & borrow) | (r0 & !borrow);
+        let r1 = (self.0[1] & borrow) | (r1 & !borrow);
+        let r2 = (self.0[2] & borrow) | (r2 & !borrow);
+        let r3 = (self.0[3] & borrow) | (r3 & !borrow);
+        let r4 = (self.0[4] & borrow) | (r4 & !borrow);
+        let r5 = (self.0[5] & borrow) | (r5 & !borrow);
+
+        Fp([r0, r1, r2, r3, r4, r5])
+    }
+
+    #[inline]
+    fn add(&self, rhs: &Fp) -> Fp {
+        let (d0, carry) = adc(self.0[0], rhs.0[0], 0);
+        let (d1, carry) = adc(self.0[1], rhs.0[1], carry);
+        let (d2, carry) = adc(self.0[2], rhs.0[2], carry);
+        let (d3, carry) = adc(self.0[3], rhs.0[3], carry);
+        let (d4, carry) = adc(self.0[4], rhs.0[4], carry);
+        let (d5, _) = adc(self.0[5], rhs.0[5], carry);
+
+        // Attempt to subtract the modulus, to ensure the value
+        // is smaller than the modulus.
+        Fp([d0, d1, d2, d3, d4, d5]).subtract_p()
+    }
+
+    #[inline]
+    fn neg(&self) -> Fp {
+        let (d0, borrow) = sbb(MODULUS[0], self.0[0], 0);
+        let (d1, borrow) = sbb(MODULUS[1], self.0[1], borrow);
+        let (d2, borrow) = sbb(MODULUS[2], self.0[2], borrow);
+        let (d3, borrow) = sbb(MODULUS[3], self.0[3], borrow);
+        let (d4, borrow) = sbb(MODULUS[4], self.0[4], borrow);
+        let (d5, _) = sbb(MODULUS[5], self.0[5], borrow);
+
+        // Let's use a mask if `self` was zero, which would mean
+        // the result of the subtraction is p.
+        let mask = (((self.0[0] | self.0[1] | self.0[2] | self.0[3] | self.0[4] | self.0[5]) == 0)
+            as u64)
+            .wrapping_sub(1);
+
+        Fp([
+            d0 & mask,
+            d1 & mask,
+            d2 & mask,
+            d3 & mask,
+            d4 & mask,
+            d5 & mask,
+        ])
+    }
+
+    #[inline]
+    fn sub(&self, rhs: &Fp) -> Fp {
+        rhs.neg().add(self)
+    }
+
+    /// Returns `c = a.zip(b).fold(0, |acc, (a_i, b_i)| acc + a_i * b_i)`.
+    ///
+    /// Implements Algorithm 2 from Patrick Longa's
+    /// [ePrint 2022-367](https://eprint.iacr.org/2022/367) §3.
+    #[inline]
+    fn sum_of_products<const T: usize>(a: [Fp; T], b: [Fp; T]) -> Fp {
+        // For a single `a x b` multiplication, operand scanning (schoolbook) takes each
+        // limb of `a` in turn, and multiplies it by all of the limbs of `b` to compute
+        // the result as a double-width intermediate representation, which is then fully
+        // reduced at the end. Here however we have pairs of multiplications (a_i, b_i),
+        // the results of which are summed.
+        //
+        // The intuition for this algorithm is two-fold:
+        // - We can interleave the operand scanning for each pair, by processing the jth
+        //   limb of each `a_i` together. As these have the same offset within the overall
+        //   operand scanning flow, their results can be summed directly.
+        // - We can interleave the multiplication and reduction steps, resulting in a
+        //   single bitshift by the limb size after each iteration. This means we only
+        //   need to store a single extra limb overall, instead of keeping around all the
+        //   intermediate results and eventually having twice as many limbs.
+
+        // Algorithm 2, line 2
+        let (u0, u1, u2, u3, u4, u5) =
+            (0..6).fold((0, 0, 0, 0, 0, 0), |(u0, u1, u2, u3, u4, u5), j| {
+                // Algorithm 2, line 3
+                // For each pair in the overall sum of products:
+                let (t0, t1, t2, t3, t4, t5, t6) = (0..T).fold(
+                    (u0, u1, u2, u3, u4, u5, 0),
+                    |(t0, t1, t2, t3, t4, t5, t6), i| {
+                        // Compute digit_j x row and accumulate into `u`.
+                        let (t0, carry) = mac(t0, a[i].0[j], b[i].0[0], 0);
+                        let (t1, carry) = mac(t1, a[i].0[j], b[i].0[1], carry);
+                        let (t2, carry) = mac(t2, a[i].0[j], b[i].0[2], carry);
+                        let (t3, carry) = mac(t3, a[i].0[j], b[i].0[3], carry);
+                        let (t4, carry) = mac(t4, a[i].0[j], b[i].0[4], carry);
+                        let (t5, carry) = mac(t5, a[i].0[j], b[i].0[5], carry);
+                        let (t6, _) = adc(t6, 0, carry);
+
+                        (t0, t1, t2, t3, t4, t5, t6)
+                    },
+                );
+
+                // Algorithm 2, lines 4-5
+                // This is a single step of the usual Montgomery reduction process.
+                let k = t0.wrapping_mul(INV);
+                let (_, carry) = mac(t0, k, MODULUS[0], 0);
+                let (r1, carry) = mac(t1, k, MODULUS[1], carry);
+                let (r2, carry) = mac(t2, k, MODULUS[2], carry);
+                let (r3, carry) = mac(t3, k, MODULUS[3], carry);
+                let (r4, carry) = mac(t4, k, MODULUS[4], carry);
+                let (r5, carry) = mac(t5, k, MODULUS[5], carry);
+                let (r6, _) = adc(t6, 0, carry);
+
+                (r1, r2, r3, r4, r5, r6)
+            });
+
+        // Because we represent F_p elements in non-redundant form, we need a final
+        // conditional subtraction to ensure the output is in range.
+        Fp([u0, u1, u2, u3, u4, u5]).subtract_p()
+    }
+
+    #[inline(always)]
+    #[allow(clippy::too_many_arguments)]
+    fn montgomery_reduce(
+        t0: u64,
+        t1: u64,
+        t2: u64,
+        t3: u64,
+        t4: u64,
+        t5: u64,
+        t6: u64,
+        t7: u64,
+        t8: u64,
+        t9: u64,
+        t10: u64,
+        t11: u64,
+    ) -> Self {
+        // The Montgomery reduction here is based on Algorithm 14.32 in
+        // Handbook of Applied Cryptography
+        // <http://cacr.uwaterloo.ca/hac/about/chap14.pdf>.
+
+        let k = t0.wrapping_mul(INV);
+        let (_, carry) = mac(t0, k, MODULUS[0], 0);
+        let (r1, carry) = mac(t1, k, MODULUS[1], carry);
+        let (r2, carry) = mac(t2, k, MODULUS[2], carry);
+        let (r3, carry) = mac(t3, k, MODULUS[3], carry);
+        let (r4, carry) = mac(t4, k, MODULUS[4], carry);
+        let (r5, carry) = mac(t5, k, MODULUS[5], carry);
+        let (r6, r7) = adc(t6, 0, carry);
+
+        let k = r1.wrapping_mul(INV);
+        let (_, carry) = mac(r1, k, MODULUS[0], 0);
+        let (r2, carry) = mac(r2, k, MODULUS[1], carry);
+        let (r3, carry) = mac(r3, k, MODULUS[2], carry);
+        let (r4, carry) = mac(r4, k, MODULUS[3], carry);
+        let (r5, carry) = mac(r5, k, MODULUS[4], carry);
+        let (r6, carry) = mac(r6, k, MODULUS[5], carry);
+        let (r7, r8) = adc(t7, r7, carry);
+
+        let k = r2.wrapping_mul(INV);
+        let (_, carry) = mac(r2, k, MODULUS[0], 0);
+        let (r3, carry) = mac(r3, k, MODULUS[1], carry);
+        let (r4, carry) = mac(r4, k, MODULUS[2], carry);
+        let (r5, carry) = mac(r5, k, MODULUS[3], carry);
+        let (r6, carry) = mac(r6, k, MODULUS[4], carry);
+        let (r7, carry) = mac(r7, k, MODULUS[5], carry);
+        let (r8, r9) = adc(t8, r8, carry);
+
+        let k = r3.wrapping_mul(INV);
+        let (_, carry) = mac(r3, k, MODULUS[0], 0);
+        let (r4, carry) = mac(r4, k, MODULUS[1], carry);
+        let (r5, carry) = mac(r5, k, MODULUS[2], carry);
+        let (r6, carry) = mac(r6, k, MODULUS[3], carry);
+        let (r7, carry) = mac(r7, k, MODULUS[4], carry);
+        let (r8, carry) = mac(r8, k, MODULUS[5], carry);
+        let (r9, r10) = adc(t9, r9, carry);
+
+        let k = r4.wrapping_mul(INV);
+        let (_, carry) = mac(r4, k, MODULUS[0], 0);
+        let (r5, carry) = mac(r5, k, MODULUS[1], carry);
+        let (r6, carry) = mac(r6, k, MODULUS[2], carry);
+        let (r7, carry) = mac(r7, k, MODULUS[3], carry);
+        let (r8, carry) = mac(r8, k, MODULUS[4], carry);
+        let (r9, carry) = mac(r9, k, MODULUS[5], carry);
+        let (r10, r11) = adc(t10, r10, carry);
+
+        let k = r5.wrapping_mul(INV);
+        let (_, carry) = mac(r5, k, MODULUS[0], 0);
+        let (r6, carry) = mac(r6, k, MODULUS[1], carry);
+        let (r7, carry) = mac(r7, k, MODULUS[2], carry);
+        let (r8, carry) = mac(r8, k, MODULUS[3], carry);
+        let (r9, carry) = mac(r9, k, MODULUS[4], carry);
+        let (r10, carry) = mac(r10, k, MODULUS[5], carry);
+        let (r11, _) = adc(t11, r11, carry);
+
+        // Attempt to subtract the modulus, to ensure the value
+        // is smaller than the modulus.
+        Fp([r6, r7, r8, r9, r10, r11]).subtract_p()
+    }
+
+    #[inline]
+    fn mul(&self, rhs: &Fp) -> Fp {
+        let (t0, carry) = mac(0, self.0[0], rhs.0[0], 0);
+        let (t1, carry) = mac(0, self.0[0], rhs.0[1], carry);
+        let (t2, carry) = mac(0, self.0[0], rhs.0[2], carry);
+        let (t3, carry) = mac(0, self.0[0], rhs.0[3], carry);
+        let (t4, carry) = mac(0, self.0[0], rhs.0[4], carry);
+        let (t5, t6) = mac(0, self.0[0], rhs.0[5], carry);
+
+        let (t1, carry) = mac(t1, self.0[1], rhs.0[0], 0);
+        let (t2, carry) = mac(t2, self.0[1], rhs.0[1], carry);
+        let (t3, carry) = mac(t3, self.0[1], rhs.0[2], carry);
+        let (t4, carry) = mac(t4, self.0[1], rhs.0[3], carry);
+        let (t5, carry) = mac(t5, self.0[1], rhs.0[4], carry);
+        let (t6, t7) = mac(t6, self.0[1], rhs.0[5], carry);
+
+        let (t2, carry) = mac(t2, self.0[2], rhs.0[0], 0);
+        let (t3, carry) = mac(t3, self.0[2], rhs.0[1], carry);
+        let (t4, carry) = mac(t4, self.0[2], rhs.0[2], carry);
+        let (t5, carry) = mac(t5, self.0[2], rhs.0[3], carry);
+        let (t6, carry) = mac(t6, self.0[2], rhs.0[4], carry);
+        let (t7, t8) = mac(t7, self.0[2], rhs.0[5], carry);
+
+        let (t3, carry) = mac(t3, self.0[3], rhs.0[0], 0);
+        let (t4, carry) = mac(t4, self.0[3], rhs.0[1], carry);
+        let (t5, carry) = mac(t5, self.0[3], rhs.0[2], carry);
+        let (t6, carry) = mac(t6, self.0[3], rhs.0[3], carry);
+        let (t7, carry) = mac(t7, self.0[3], rhs.0[4], carry);
+        let (t8, t9) = mac(t8, self.0[3], rhs.0[5], carry);
+
+        let (t4, carry) = mac(t4, self.0[4], rhs.0[0], 0);
+        let (t5, carry) = mac(t5, self.0[4], rhs.0[1], carry);
+        let (t6, carry) = mac(t6, self.0[4], rhs.0[2], carry);
+        let (t7, carry) = mac(t7, self.0[4], rhs.0[3], carry);
+        let (t8, carry) = mac(t8, self.0[4], rhs.0[4], carry);
+        let (t9, t10) = mac(t9, self.0[4], rhs.0[5], carry);
+
+        let (t5, carry) = mac(t5, self.0[5], rhs.0[0], 0);
+        let (t6, carry) = mac(t6, self.0[5], rhs.0[1], carry);
+        let (t7, carry) = mac(t7, self.0[5], rhs.0[2], carry);
+        let (t8, carry) = mac(t8, self.0[5], rhs.0[3], carry);
+        let (t9, carry) = mac(t9, self.0[5], rhs.0[4], carry);
+        let (t10, t11) = mac(t10, self.0[5], rhs.0[5], carry);
+
+        Self::montgomery_reduce(t0, t1, t2, t3, t4, t5, t6, t7, t8, t9, t10, t11)
+    }
+}
+
+/// c0 + c1·u where u² = -1.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Fp2 {
+    c0: Fp,
+    c1: Fp,
+}
+
+impl ConditionallySelectable for Fp2 {
+    fn conditional_select(a: &Self, b: &Self, choice: Choice) -> Self {
+        Fp2 {
+            c0: Fp::conditional_select(&a.c0, &b.c0, choice),
+            c1: Fp::conditional_select(&a.c1, &b.c1, choice),
+        }
+    }
+}
+
+impl Fp2 {
+    const ZERO: Fp2 = Fp2 {
+        c0: Fp::ZERO,
+        c1: Fp::ZERO,
+    };
+
+    fn is_zero(&self) -> bool {
+        self.c0.is_zero() && self.c1.is_zero()
+    }
+
+    fn lexicographically_largest(&self) -> bool {
+        self.c1.lexicographically_largest()
+            || (self.c1.is_zero() && self.c0.lexicographically_largest())
+    }
+
+    #[inline]
+    fn add(&self, rhs: &Fp2) -> Fp2 {
+        Fp2 {
+            c0: self.c0.add(&rhs.c0),
+            c1: self.c1.add(&rhs.c1),
+        }
+    }
+
+    #[inline]
+    fn sub(&self, rhs: &Fp2) -> Fp2 {
+        Fp2 {
+            c0: self.c0.sub(&rhs.c0),
+            c1: self.c1.sub(&rhs.c1),
+        }
+    }
+
+    #[inline]
+    fn neg(&self) -> Fp2 {
+        Fp2 {
+            c0: self.c0.neg(),
+            c1: self.c1.neg(),
+        }
+    }
+
+    #[inline]
+    fn square(&self) -> Fp2 {
+        // (c0 + c1)(c0 - c1) + 2·c0·c1·u
+        let a = self.c0.add(&self.c1);
+        let b = self.c0.sub(&self.c1);
+        let c = self.c0.add(&self.c0);
+        Fp2 {
+            c0: a.mul(&b),
+            c1: c.mul(&self.c1),
+        }
+    }
+
+    /// Multiplies by u + 1.
+    #[inline]
+    fn mul_by_nonresidue(&self) -> Fp2 {
+        Fp2 {
+            c0: self.c0.sub(&self.c1),
+            c1: self.c0.add(&self.c1),
+        }
+    }
+}
+
+/// c0 + c1·v + c2·v² where v³ = u + 1.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Fp6 {
+    c0: Fp2,
+    c1: Fp2,
+    c2: Fp2,
+}
+
+impl ConditionallySelectable for Fp6 {
+    fn conditional_select(a: &Self, b: &Self, choice: Choice) -> Self {
+        Fp6 {
+            c0: Fp2::conditional_select(&a.c0, &b.c0, choice),
+            c1: Fp2::conditional_select(&a.c1, &b.c1, choice),
+            c2: Fp2::conditional_select(&a.c2, &b.c2, choice),
+        }
+    }
+}
+
+impl Fp6 {
+    const ZERO: Fp6 = Fp6 {
+        c0: Fp2::ZERO,
+        c1: Fp2::ZERO,
+        c2: Fp2::ZERO,
+    };
+
+    fn from_bytes(bytes: &[u8]) -> Fp6 {
+        let fp = |i: usize| Fp::from_bytes(bytes[48 * i..48 * (i + 1)].try_into().unwrap());
+        Fp6 {
+            c0: Fp2 {
+                c0: fp(0),
+                c1: fp(1),
+            },
+            c1: Fp2 {
+                c0: fp(2),
+                c1: fp(3),
+            },
+            c2: Fp2 {
+                c0: fp(4),
+                c1: fp(5),
+            },
+        }
+    }
+
+    fn to_bytes(self) -> [u8; 288] {
+        let mut bytes = [0; 288];
+        let coefficients = [
+            self.c0.c0, self.c0.c1, self.c1.c0, self.c1.c1, self.c2.c0, self.c2.c1,
+        ];
+        for (chunk, coefficient) in bytes.chunks_mut(48).zip(coefficients) {
+            chunk.copy_from_slice(&coefficient.to_bytes());
+        }
+        bytes
+    }
+
+    fn lexicographically_largest(&self) -> bool {
+        self.c2.lexicographically_largest()
+            || (self.c2.is_zero() && self.c1.lexicographically_largest())
+            || (self.c2.is_zero() && self.c1.is_zero() && self.c0.lexicographically_largest())
+    }
+
+    #[inline]
+    fn add(&self, rhs: &Fp6) -> Fp6 {
+        Fp6 {
+            c0: self.c0.add(&rhs.c0),
+            c1: self.c1.add(&rhs.c1),
+            c2: self.c2.add(&rhs.c2),
+        }
+    }
+
+    #[inline]
+    fn sub(&self, rhs: &Fp6) -> Fp6 {
+        Fp6 {
+            c0: self.c0.sub(&rhs.c0),
+            c1: self.c1.sub(&rhs.c1),
+            c2: self.c2.sub(&rhs.c2),
+        }
+    }
+
+    #[inline]
+    fn neg(&self) -> Fp6 {
+        Fp6 {
+            c0: self.c0.neg(),
+            c1: self.c1.neg(),
+            c2: self.c2.neg(),
+        }
+    }
+
+    #[inline]
+    fn mul(&self, rhs: &Fp6) -> Fp6 {
+        // every coefficient of the product written as a sum of products in Fp, as the curve library
+        // does it
+        let (a, b) = (self, rhs);
+        let b10_p_b11 = b.c1.c0.add(&b.c1.c1);
+        let b10_m_b11 = b.c1.c0.sub(&b.c1.c1);
+        let b20_p_b21 = b.c2.c0.add(&b.c2.c1);
+        let b20_m_b21 = b.c2.c0.sub(&b.c2.c1);
+        let a_conj = [
+            a.c0.c0,
+            a.c0.c1.neg(),
+            a.c1.c0,
+            a.c1.c1.neg(),
+            a.c2.c0,
+            a.c2.c1.neg(),
+        ];
+        let a = [a.c0.c0, a.c0.c1, a.c1.c0, a.c1.c1, a.c2.c0, a.c2.c1];
+
+        Fp6 {
+            c0: Fp2 {
+                c0: Fp::sum_of_products(
+                    a_conj,
+                    [b.c0.c0, b.c0.c1, b20_m_b21, b20_p_b21, b10_m_b11, b10_p_b11],
+                ),
+                c1: Fp::sum_of_products(
+                    a,
+                    [b.c0.c1, b.c0.c0, b20_p_b21, b20_m_b21, b10_p_b11, b10_m_b11],
+                ),
+            },
+            c1: Fp2 {
+                c0: Fp::sum_of_products(
+                    a_conj,
+                    [b.c1.c0, b.c1.c1, b.c0.c0, b.c0.c1, b20_m_b21, b20_p_b21],
+                ),
+                c1: Fp::sum_of_products(
+                    a,
+                    [b.c1.c1, b.c1.c0, b.c0.c1, b.c0.c0, b20_p_b21, b20_m_b21],
+                ),
+            },
+            c2: Fp2 {
+                c0: Fp::sum_of_products(
+                    a_conj,
+                    [b.c2.c0, b.c2.c1, b.c1.c0, b.c1.c1, b.c0.c0, b.c0.c1],
+                ),
+                c1: Fp::sum_of_products(a, [b.c2.c1, b.c2.c0, b.c1.c1, b.c1.c0, b.c0.c1, b.c0.c0]),
+            },
+        }
+    }
+
+    /// Multiplies by v.
+    #[inline]
+    fn mul_by_nonresidue(&self) -> Fp6 {
+        Fp6 {
+            c0: self.c2.mul_by_nonresidue(),
+            c1: self.c0,
+            c2: self.c1,
+        }
+    }
+}
+
+/// An element of Gt as c0 + c1·w where w² = v.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct Fp12 {
+    c0: Fp6,
+    c1: Fp6,
+}
+
+impl ConditionallySelectable for Fp12 {
+    fn conditional_select(a: &Self, b: &Self, choice: Choice) -> Self {
+        Fp12 {
+            c0: Fp6::conditional_select(&a.c0, &b.c0, choice),
+            c1: Fp6::conditional_select(&a.c1, &b.c1, choice),
+        }
+    }
+}
+
+impl From<&Gt> for Fp12 {
+    fn from(element: &Gt) -> Self {
+        let bytes = element.to_uncompressed();
+        Fp12 {
+            c0: Fp6::from_bytes(&bytes[..288]),
+            c1: Fp6::from_bytes(&bytes[288..]),
+        }
+    }
+}
+
+impl Fp12 {
+    fn one() -> Fp12 {
+        let mut one = Fp12 {
+            c0: Fp6::ZERO,
+            c1: Fp6::ZERO,
+        };
+        one.c0.c0.c0 = R;
+        one
+    }
+
+    /// The same encoding as [`Gt::to_compressed`].
+    pub(crate) fn to_compressed(self) -> [u8; 288] {
+        let mut bytes = self.c1.to_bytes();
+        bytes[0] |= 1 << 7;
+        if self.c0.lexicographically_largest() {
+            bytes[0] |= 1 << 6;
+        }
+        bytes
+    }
+
+    pub(crate) fn mul(&self, rhs: &Fp12) -> Fp12 {
+        let aa = self.c0.mul(&rhs.c0);
+        let bb = self.c1.mul(&rhs.c1);
+        let cross = self.c0.add(&self.c1).mul(&rhs.c0.add(&rhs.c1));
+        Fp12 {
+            c0: bb.mul_by_nonresidue().add(&aa),
+            c1: cross.sub(&aa).sub(&bb),
+        }
+    }
+
+    /// The inverse of an element of Gt.
+    pub(crate) fn conjugate(&self) -> Fp12 {
+        Fp12 {
+            c0: self.c0,
+            c1: self.c1.neg(),
+        }
+    }
+
+    /// Squaring that's only right for elements of the cyclotomic subgroup (Algorithm 5.5.4 of the
+    /// Guide to Pairing-Based Cryptography, as the curve library does it).
+    fn cyclotomic_square(&self) -> Fp12 {
+        fn fp4_square(a: Fp2, b: Fp2) -> (Fp2, Fp2) {
+            let t0 = a.square();
+            let t1 = b.square();
+            let c0 = t1.mul_by_nonresidue().add(&t0);
+            let c1 = a.add(&b).square().sub(&t0).sub(&t1);
+            (c0, c1)
+        }
+        // 2·t - z and 2·t + z
+        fn double_sub(t: Fp2, z: Fp2) -> Fp2 {
+            let z = t.sub(&z);
+            z.add(&z).add(&t)
+        }
+        fn double_add(t: Fp2, z: Fp2) -> Fp2 {
+            let z = t.add(&z);
+            z.add(&z).add(&t)
+        }
+
+        let (z0, z4, z3) = (self.c0.c0, self.c0.c1, self.c0.c2);
+        let (z2, z1, z5) = (self.c1.c0, self.c1.c1, self.c1.c2);
+
+        let (t0, t1) = fp4_square(z0, z1);
+        let z0 = double_sub(t0, z0);
+        let z1 = double_add(t1, z1);
+
+        let (t0, t1) = fp4_square(z2, z3);
+        let (t2, t3) = fp4_square(z4, z5);
+        let z4 = double_sub(t0, z4);
+        let z5 = double_add(t1, z5);
+        let z2 = double_add(t3.mul_by_nonresidue(), z2);
+        let z3 = double_sub(t2, z3);
+
+        Fp12 {
+            c0: Fp6 {
+                c0: z0,
+                c1: z4,
+                c2: z3,
+            },
+            c1: Fp6 {
+                c0: z2,
+                c1: z1,
+                c2: z5,
+            },
+        }
+    }
+
+    /// `self^exponent` (`exponent·self` in the additive notation of [`Gt`]) in constant time. Only
+    /// right if `self` is in Gt.
+    pub(crate) fn pow(&self, exponent: &Scalar) -> Fp12 {
+        let mut table = [Fp12::one(); 1 << WINDOW_WIDTH];
+        for j in 1..table.len() {
+            table[j] = table[j - 1].mul(self);
+        }
+        let exponent = exponent.to_bytes();
+        let mut acc = Fp12::one();
+        for (i, byte) in exponent.iter().rev().enumerate() {
+            for (j, window) in [byte >> 4, byte & 0xf].into_iter().enumerate() {
+                if i + j > 0 {
+                    for _ in 0..WINDOW_WIDTH {
+                        acc = acc.cyclotomic_square();
+                    }
+                }
+                let mut element = table[0];
+                for (k, entry) in table.iter().enumerate() {
+                    element.conditional_assign(entry, window.ct_eq(&(k as u8)));
+                }
+                acc = acc.mul(&element);
+            }
+        }
+        acc
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use ff::Field;
+    use group::Group;
+
+    #[test]
+    fn pow_matches_gt() {
+        let rng = &mut rand::thread_rng();
+        let base = Gt::random(&mut *rng);
+        for exponent in [
+            Scalar::zero(),
+            Scalar::one(),
+            -Scalar::one(),
+            Scalar::random(&mut *rng),
+        ] {
+            let expected = base * exponent;
+            assert_eq!(Fp12::from(&base).pow(&exponent), Fp12::from(&expected));
+            assert_eq!(
+                Fp12::from(&base).pow(&exponent).to_compressed(),
+                expected.to_compressed()
+            );
+        }
+        let other = Gt::random(&mut *rng);
+        assert_eq!(
+            Fp12::from(&base).mul(&Fp12::from(&other).conjugate()),
+            Fp12::from(&(base - other))
+        );
+    }
+}
@@ -1,17 +1,24 @@
-use bls12_381::{G1Affine, Gt, Scalar};
+// sigma_fun still exposes generic-array 0.14 which is deprecated upstream
+#![allow(deprecated)]
+use crate::cyclotomic::Fp12;
+use bls12_381::{G1Affine, G1Projective, Gt, Scalar};
 use ff::Field;
 use group::GroupEncoding;
 use rand::{CryptoRng, RngCore};
 use rand_chacha::ChaCha20Rng;
-use sha2::{digest::Update, Sha256};
+use sha2::{
+    digest::{Digest, Update},
+    Sha256,
+};
 use sigma_fun::{
     generic_array::{ArrayLength, GenericArray},
     typenum::{self, type_operators::IsLessOrEqual, U31},
-    CompactProof, Eq, FiatShamir, HashTranscript, Sigma,
+    CompactProof, Eq, FiatShamir, HashTranscript, ProverTranscript, Sigma, Transcript,
 };
 use std::marker::PhantomData;
 
-/// DL Proof for bls12-381 target group
+/// DL Proof for bls12-381 target group. Announcements are kept compressed since they're only ever
+/// hashed.
 #[derive(Clone, Debug, Default, PartialEq)]
 pub struct DLGT<L> {
     challenge_len: PhantomData<L>,
@@ -31,7 +38,7 @@ where
     type Witness = Scalar;
     type Statement = (Gt, Gt);
     type AnnounceSecret = Scalar;
-    type Announcement = Gt;
+    type Announcement = [u8; 288];
     type Response = Scalar;
     type ChallengeLength = L;
 
@@ -52,8 +59,8 @@ where
         statement: &Self::Statement,
         announce_secret: &Self::AnnounceSecret,
     ) -> Self::Announcement {
-        let G = &statement.0;
-        G * announce_secret
+        let G = Fp12::from(&statement.0);
+        G.pow(announce_secret).to_compressed()
     }
 
     fn sample_response<Rng: CryptoRng + RngCore>(&self, rng: &mut Rng) -> Self::Response {
@@ -68,7 +75,10 @@ where
     ) -> Option<Self::Announcement> {
         let (G, X) = statement;
         let challenge = normalize_challenge(challenge);
-        Some(G * response - X * challenge)
+        let announcement = Fp12::from(G)
+            .pow(response)
+            .mul(&Fp12::from(X).pow(&challenge).conjugate());
+        Some(announcement.to_compressed())
     }
 
     fn hash_statement<H: Update>(&self, hash: &mut H, statement: &Self::Statement) {
@@ -77,7 +87,7 @@ where
     }
 
     fn hash_announcement<H: Update>(&self, hash: &mut H, announcement: &Self::Announcement) {
-        hash.update(announcement.to_compressed());
+        hash.update(announcement);
     }
 
     fn hash_witness<H: Update>(&self, hash: &mut H, witness: &Self::Witness) {
@@ -180,12 +190,124 @@ fn normalize_challenge<L: ArrayLength<u8>>(challenge: &GenericArray<u8, L>) -> S
     Scalar::from_bytes(&challenge_bytes).unwrap()
 }
 
-type DLEQ = Eq<DLG1<U31>, DLGT<U31>>;
+type Dleq = Eq<DLG1<U31>, DLGT<U31>>;
+
+pub type ProofSystem = FiatShamir<Dleq, HashTranscript<Sha256, ChaCha20Rng>>;
+
+/// A proof made with [`prove_eqaulity`].
+#[derive(Clone, Debug, PartialEq)]
+// a contract uses one kind of proof throughout so boxing the big variant wouldn't save memory
+#[allow(clippy::large_enum_variant)]
+pub enum Proof {
+    /// The challenge and response ([`COMPACT_PROOF_LEN`] bytes). Each one is checked on its own.
+    Compact(CompactProof<Dleq>),
+    /// The prover's announcements and response ([`BATCHABLE_PROOF_LEN`] bytes). They're over five
+    /// times the size of compact proofs but thousands of them can be checked at once with
+    /// [`verify_eqaulity_batch`].
+    Batchable {
+        announcement: (G1Affine, Gt),
+        response: Scalar,
+    },
+}
+
+struct ContractFingerprint<'a>(&'a [u8; 32]);
+
+impl sigma_fun::Writable for ContractFingerprint<'_> {
+    fn write_to<W: core::fmt::Write>(&self, w: &mut W) -> core::fmt::Result {
+        write!(w, "dlc-venc-pairing-contract(")?;
+        for byte in self.0 {
+            write!(w, "{:02x}", byte)?;
+        }
+        write!(w, ")")
+    }
+}
+
+/// The proof system for the contract with the given [fingerprint]. The fingerprint is absorbed into
+/// the transcript before anything else so a proof made for one contract (or event) won't verify
+/// for any other.
+///
+/// [fingerprint]: crate::common::Params::fingerprint
+pub fn proof_system(contract_fingerprint: &[u8; 32]) -> ProofSystem {
+    let mut transcript = HashTranscript::<Sha256, ChaCha20Rng>::default();
+    Transcript::<Dleq>::add_name(&mut transcript, &ContractFingerprint(contract_fingerprint));
+    ProofSystem::new(Dleq::default(), transcript, None)
+}
+
+/// The length of an encoded [`Proof::Compact`]: a 31 byte challenge followed by the response.
+pub const COMPACT_PROOF_LEN: usize = 31 + 32;
+
+/// The length of an encoded [`Proof::Batchable`]: the G1 and Gt announcements followed by the
+/// response.
+pub const BATCHABLE_PROOF_LEN: usize = 48 + 288 + 32;
+
+/// Encodes a proof as [`COMPACT_PROOF_LEN`] or [`BATCHABLE_PROOF_LEN`] bytes depending on its kind.
+pub fn proof_to_bytes(proof: &Proof) -> Vec<u8> {
+    match proof {
+        Proof::Compact(proof) => [proof.challenge.as_slice(), &proof.response.to_bytes()].concat(),
+        Proof::Batchable {
+            announcement,
+            response,
+        } => [
+            &announcement.0.to_compressed()[..],
+            &announcement.1.to_compressed(),
+            &response.to_bytes(),
+        ]
+        .concat(),
+    }
+}
+
+pub fn compact_proof_from_bytes(bytes: &[u8; COMPACT_PROOF_LEN]) -> Option<Proof> {
+    let response = Option::from(Scalar::from_bytes(bytes[31..].try_into().unwrap()))?;
+    Some(Proof::Compact(CompactProof {
+        challenge: GenericArray::clone_from_slice(&bytes[..31]),
+        response,
+    }))
+}
 
-pub type Proof = CompactProof<DLEQ>;
+/// Decodes a batchable proof.
+///
+/// The Gt announcement isn't checked to be in the prime order subgroup since that costs as much as
+/// an exponentiation. It doesn't need to be: a proof only verifies (on its own or in a batch) if
+/// the prime order part of the announcement is right and the rest of it is irrelevant.
+pub fn batchable_proof_from_bytes(bytes: &[u8; BATCHABLE_PROOF_LEN]) -> Option<Proof> {
+    let g1 = Option::from(G1Affine::from_compressed(bytes[..48].try_into().unwrap()))?;
+    let gt = Option::from(Gt::from_compressed_unchecked(
+        bytes[48..336].try_into().unwrap(),
+    ))?;
+    let response = Option::from(Scalar::from_bytes(bytes[336..].try_into().unwrap()))?;
+    Some(Proof::Batchable {
+        announcement: (g1, gt),
+        response,
+    })
+}
 
-pub type ProofSystem = FiatShamir<DLEQ, HashTranscript<Sha256, ChaCha20Rng>>;
+type Statement = ((G1Affine, G1Affine), (Gt, Gt));
 
+fn statement(
+    ri_encryption: Gt,
+    sig_point: Gt,
+    commit_base: Gt,
+    commit: (G1Affine, Gt),
+) -> Statement {
+    let enc_sub = ri_encryption - commit.1;
+    let sig_sub = sig_point - commit_base;
+    ((G1Affine::generator(), commit.0), (sig_sub, enc_sub))
+}
+
+fn challenge(
+    proof_system: &ProofSystem,
+    statement: &Statement,
+    announcement: &(G1Affine, Gt),
+) -> Scalar {
+    let mut transcript = proof_system.transcript.clone();
+    transcript.add_statement(&proof_system.sigma, statement);
+    let announcement = (announcement.0, announcement.1.to_compressed());
+    normalize_challenge(&transcript.get_challenge(&proof_system.sigma, &announcement))
+}
+
+/// Proves the encryption and the commitment are of the same value, keeping the announcements in
+/// the proof if `batchable`.
+#[allow(clippy::too_many_arguments)]
 pub fn prove_eqaulity(
     proof_system: &ProofSystem,
     ri_prime: Scalar,
@@ -193,14 +315,42 @@ pub fn prove_eqaulity(
     sig_point: Gt,
     commit_base: Gt,
     commit: (G1Affine, Gt),
+    batchable: bool,
+    rng: &mut (impl RngCore + CryptoRng),
 ) -> Proof {
-    let enc_sub = &ri_encryption - &commit.1;
-    let sig_sub = &sig_point - &commit_base;
-    let statement = ((G1Affine::generator(), commit.0), (sig_sub, enc_sub));
+    let statement = statement(ri_encryption, sig_point, commit_base, commit);
     let witness = ri_prime;
-
-    let proof = proof_system.prove(&witness, &statement, Some(&mut rand::thread_rng()));
-    proof
+    let sigma = &proof_system.sigma;
+
+    // this is what `FiatShamir::prove` does except we keep the announcement
+    let mut transcript = proof_system.transcript.clone();
+    transcript.add_statement(sigma, &statement);
+    let mut transcript_rng = transcript.gen_rng(sigma, &witness, Some(rng));
+    let announce_secret = sigma.gen_announce_secret(&witness, &mut transcript_rng);
+    if batchable {
+        // the proof keeps the Gt announcement so it has to be made with the curve library
+        let ((G, _), (H, _)) = statement;
+        let announcement = ((G * announce_secret).into(), H * announce_secret);
+        let challenge = self::challenge(proof_system, &statement, &announcement);
+        Proof::Batchable {
+            announcement,
+            response: announce_secret + challenge * witness,
+        }
+    } else {
+        let announcement = sigma.announce(&statement, &announce_secret);
+        let challenge = transcript.get_challenge(sigma, &announcement);
+        let response = sigma.respond(
+            &witness,
+            &statement,
+            announce_secret,
+            &announcement,
+            &challenge,
+        );
+        Proof::Compact(CompactProof {
+            challenge,
+            response,
+        })
+    }
 }
 
 pub fn verify_eqaulity(
@@ -211,11 +361,101 @@ pub fn verify_eqaulity(
     commit_base: Gt,
     commit: (G1Affine, Gt),
 ) -> bool {
-    let enc_sub = &ri_encryption - &commit.1;
-    let sig_sub = &sig_point - &commit_base;
-    let statement = ((G1Affine::generator(), commit.0), (sig_sub, enc_sub));
+    let statement = statement(ri_encryption, sig_point, commit_base, commit);
+    match proof {
+        Proof::Compact(proof) => proof_system.verify(&statement, proof),
+        Proof::Batchable {
+            announcement,
+            response,
+        } => {
+            let ((G, X1), (H, X2)) = statement;
+            let c = challenge(proof_system, &statement, announcement);
+            let X2_c = Fp12::from(&X2).pow(&c);
+            G * response == announcement.0 + X1 * c
+                && Fp12::from(&H).pow(response) == Fp12::from(&announcement.1).mul(&X2_c)
+        }
+    }
+}
 
-    proof_system.verify(&statement, proof)
+/// Checks many proofs at once. Each item is `(proof, ri_encryption, sig_point, commit)` as they
+/// would be passed to [`verify_eqaulity`] and they must all have the same `commit_base`.
+///
+/// Rather than checking `z·G = A₁ + c·X₁` and `z·H = A₂ + c·X₂` for every proof we check a random
+/// linear combination of each with one multi-scalar multiplication in G1 and one in Gt. Items with
+/// the same `sig_point` should be next to each other since runs of them share a single term for `H`.
+///
+/// This only tells you whether every proof is valid (and it's false if any of them are compact).
+/// Use [`verify_eqaulity`] to find out which one isn't.
+pub fn verify_eqaulity_batch(
+    proof_system: &ProofSystem,
+    commit_base: Gt,
+    items: &[(&Proof, Gt, Gt, (G1Affine, Gt))],
+) -> bool {
+    let proofs = match items
+        .iter()
+        .map(|(proof, ..)| match proof {
+            Proof::Batchable {
+                announcement,
+                response,
+            } => Some((announcement, response)),
+            Proof::Compact(_) => None,
+        })
+        .collect::<Option<Vec<_>>>()
+    {
+        Some(proofs) => proofs,
+        None => return false,
+    };
+    let statements = crate::parallel::map(
+        items.iter().zip(&proofs).collect(),
+        |((_, ri_encryption, sig_point, commit), (announcement, _))| {
+            let statement = statement(*ri_encryption, *sig_point, commit_base, *commit);
+            let challenge = challenge(proof_system, &statement, announcement);
+            (statement, challenge)
+        },
+    );
+
+    // The challenges commit to the statements and announcements so with the responses they commit
+    // to the whole batch.
+    let mut seed = Sha256::default();
+    Update::update(&mut seed, b"dlc-venc-pairing/dleq-batch");
+    for ((_, response), (_, challenge)) in proofs.iter().zip(&statements) {
+        Update::update(&mut seed, challenge.to_bytes().as_ref());
+        Update::update(&mut seed, response.to_bytes().as_ref());
+    }
+    let seed = Digest::finalize(seed);
+
+    let mut g1_scalars = vec![Scalar::zero()];
+    let mut g1_points = vec![G1Projective::generator()];
+    let mut gt_scalars = vec![];
+    let mut gt_points = vec![];
+    let mut last_H = None;
+    for (i, ((announcement, response), (((_, X1), (H, X2)), c))) in
+        proofs.iter().zip(&statements).enumerate()
+    {
+        let rho = crate::msm::batch_coefficient(&seed, i as u32);
+        let rho_z = rho * *response;
+        let rho_c = rho * c;
+
+        g1_scalars[0] += rho_z;
+        g1_scalars.extend([-rho, -rho_c]);
+        g1_points.extend([G1Projective::from(announcement.0), G1Projective::from(X1)]);
+
+        if last_H == Some(*H) {
+            *gt_scalars.last_mut().unwrap() += rho_z;
+        } else {
+            gt_scalars.push(rho_z);
+            gt_points.push(*H);
+            last_H = Some(*H);
+        }
+        // keep the running coefficient for `H` last
+        let H_coefficient = gt_scalars.pop().unwrap();
+        let H = gt_points.pop().unwrap();
+        gt_scalars.extend([-rho, -rho_c, H_coefficient]);
+        gt_points.extend([announcement.1, *X2, H]);
+    }
+
+    bool::from(crate::msm::msm(&g1_scalars, &g1_points).is_identity())
+        && crate::msm::msm(&gt_scalars, &gt_points) == Gt::identity()
 }
 
 #[cfg(test)]
@@ -234,23 +474,142 @@ mod test {
             commit_base * ri_prime + ri_point,
         );
         let ri_encryption = sig_point * ri_prime + ri_point;
-        let proof_system = ProofSystem::default();
+        let proof_system = proof_system(&[1u8; 32]);
+
+        for batchable in [false, true] {
+            let proof = prove_eqaulity(
+                &proof_system,
+                ri_prime,
+                ri_encryption,
+                sig_point,
+                commit_base,
+                commit,
+                batchable,
+                &mut rand::thread_rng(),
+            );
+            assert!(verify_eqaulity(
+                &proof_system,
+                &proof,
+                ri_encryption,
+                sig_point,
+                commit_base,
+                commit
+            ));
+
+            let bytes = proof_to_bytes(&proof);
+            let decoded = if batchable {
+                batchable_proof_from_bytes(&bytes.try_into().unwrap())
+            } else {
+                compact_proof_from_bytes(&bytes.try_into().unwrap())
+            };
+            assert_eq!(decoded.unwrap(), proof);
+
+            let other_contract = super::proof_system(&[2u8; 32]);
+            assert!(!verify_eqaulity(
+                &other_contract,
+                &proof,
+                ri_encryption,
+                sig_point,
+                commit_base,
+                commit
+            ));
+        }
+    }
 
+    #[test]
+    fn batch_verification() {
+        let rng = &mut rand::thread_rng();
+        let proof_system = proof_system(&[1u8; 32]);
+        let commit_base = Gt::random(&mut *rng);
+        let sig_points = [Gt::random(&mut *rng), Gt::random(&mut *rng)];
+        let items = (0..6)
+            .map(|i| {
+                let ri_prime = Scalar::random(&mut *rng);
+                let ri_point = Gt::random(&mut *rng);
+                let sig_point = sig_points[i / 3];
+                let commit = (
+                    (G1Affine::generator() * ri_prime).into(),
+                    commit_base * ri_prime + ri_point,
+                );
+                let ri_encryption = sig_point * ri_prime + ri_point;
+                let proof = prove_eqaulity(
+                    &proof_system,
+                    ri_prime,
+                    ri_encryption,
+                    sig_point,
+                    commit_base,
+                    commit,
+                    true,
+                    rng,
+                );
+                (proof, ri_encryption, sig_point, commit)
+            })
+            .collect::<Vec<_>>();
+        fn batch(
+            items: &[(Proof, Gt, Gt, (G1Affine, Gt))],
+        ) -> Vec<(&Proof, Gt, Gt, (G1Affine, Gt))> {
+            items
+                .iter()
+                .map(|(proof, ri_encryption, sig_point, commit)| {
+                    (proof, *ri_encryption, *sig_point, *commit)
+                })
+                .collect()
+        }
+        assert!(verify_eqaulity_batch(
+            &proof_system,
+            commit_base,
+            &batch(&items)
+        ));
+
+        let mut bad_items = items.clone();
+        bad_items[4].1 += Gt::generator();
+        assert!(!verify_eqaulity_batch(
+            &proof_system,
+            commit_base,
+            &batch(&bad_items)
+        ));
+
+        let mut bad_items = items.clone();
+        if let Proof::Batchable { response, .. } = &mut bad_items[1].0 {
+            *response += Scalar::one();
+        }
+        assert!(!verify_eqaulity_batch(
+            &proof_system,
+            commit_base,
+            &batch(&bad_items)
+        ));
+
+        // compact proofs have to be checked one by one
+        let mut compact_items = items;
+        let ri_prime = Scalar::random(&mut *rng);
+        let commit = (
+            (G1Affine::generator() * ri_prime).into(),
+            commit_base * ri_prime,
+        );
+        let ri_encryption = sig_points[0] * ri_prime;
         let proof = prove_eqaulity(
             &proof_system,
             ri_prime,
             ri_encryption,
-            sig_point,
+            sig_points[0],
             commit_base,
             commit,
+            false,
+            rng,
         );
         assert!(verify_eqaulity(
             &proof_system,
             &proof,
             ri_encryption,
-            sig_point,
+            sig_points[0],
             commit_base,
             commit
-        ))
+        ));
+        compact_items[2] = (proof, ri_encryption, sig_points[0], commit);
+        assert!(!verify_eqaulity_batch(
+            &proof_system,
+            commit_base,
+            &batch(&compact_items)
+        ));
     }
 }
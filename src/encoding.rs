@@ -0,0 +1,258 @@
+//! Helpers for the byte-exact binary encoding used on the wire.
+//!
+//! Everything is fixed width and big-endian. Variable length lists are prefixed by their length as
+//! a `u32`. Group elements are always in compressed form and are checked to be in the right
+//! subgroup when they are read back.
+use anyhow::anyhow;
+use bls12_381::{G1Affine, G2Affine, Gt, Scalar};
+use secp256kfun::{marker::*, Point, Scalar as ChainScalar};
+
+use sha2::{digest::Digest, Sha256};
+
+use crate::messages::WIRE_VERSION;
+
+const CHECKSUM_LEN: usize = 32;
+
+/// Writes the wire version followed by the type of the thing being encoded.
+pub(crate) fn write_header(writer: &mut Writer, tag: u8) {
+    writer.u8(WIRE_VERSION);
+    writer.u8(tag);
+}
+
+pub(crate) fn read_header(reader: &mut Reader, expected_tag: u8) -> anyhow::Result<()> {
+    let version = reader.u8()?;
+    if version != WIRE_VERSION {
+        return Err(anyhow!(
+            "unsupported wire version {} (expected {})",
+            version,
+            WIRE_VERSION
+        ));
+    }
+    let tag = reader.u8()?;
+    if tag != expected_tag {
+        return Err(anyhow!(
+            "expected message type {} but got {}",
+            expected_tag,
+            tag
+        ));
+    }
+    Ok(())
+}
+
+fn checksum(bytes: &[u8]) -> [u8; CHECKSUM_LEN] {
+    Sha256::default()
+        .chain(b"dlc-venc-pairing/checksum")
+        .chain(bytes)
+        .finalize()
+        .into()
+}
+
+/// Appends a checksum to encoded state that is going to be stored so corruption can be detected
+/// when it's loaded with [`strip_checksum`].
+pub(crate) fn append_checksum(mut bytes: Vec<u8>) -> Vec<u8> {
+    let checksum = checksum(&bytes);
+    bytes.extend_from_slice(&checksum);
+    bytes
+}
+
+pub(crate) fn strip_checksum(bytes: &[u8]) -> anyhow::Result<&[u8]> {
+    if bytes.len() < CHECKSUM_LEN {
+        return Err(anyhow!("input is too short to have a checksum"));
+    }
+    let (body, got) = bytes.split_at(bytes.len() - CHECKSUM_LEN);
+    if checksum(body) != got {
+        return Err(anyhow!("checksum didn't match -- the data is corrupted"));
+    }
+    Ok(body)
+}
+
+#[derive(Default)]
+pub(crate) struct Writer(Vec<u8>);
+
+impl Writer {
+    pub fn u8(&mut self, byte: u8) {
+        self.0.push(byte)
+    }
+
+    pub fn bool(&mut self, value: bool) {
+        self.u8(value as u8)
+    }
+
+    pub fn u16(&mut self, int: u16) {
+        self.0.extend_from_slice(&int.to_be_bytes())
+    }
+
+    pub fn u32(&mut self, int: u32) {
+        self.0.extend_from_slice(&int.to_be_bytes())
+    }
+
+    pub fn u64(&mut self, int: u64) {
+        self.0.extend_from_slice(&int.to_be_bytes())
+    }
+
+    pub fn len(&mut self, len: usize) {
+        self.u32(u32::try_from(len).expect("lists are never that long"))
+    }
+
+    pub fn bytes(&mut self, bytes: &[u8]) {
+        self.0.extend_from_slice(bytes)
+    }
+
+    pub fn str(&mut self, string: &str) {
+        self.len(string.len());
+        self.bytes(string.as_bytes())
+    }
+
+    pub fn g1(&mut self, point: &G1Affine) {
+        self.bytes(&point.to_compressed())
+    }
+
+    pub fn g2(&mut self, point: &G2Affine) {
+        self.bytes(&point.to_compressed())
+    }
+
+    pub fn gt(&mut self, elem: &Gt) {
+        self.bytes(&elem.to_compressed())
+    }
+
+    pub fn scalar(&mut self, scalar: &Scalar) {
+        self.bytes(&scalar.to_bytes())
+    }
+
+    pub fn point(&mut self, point: &Point) {
+        self.bytes(&point.to_bytes())
+    }
+
+    pub fn chain_scalar<S, Z>(&mut self, scalar: &ChainScalar<S, Z>) {
+        self.bytes(&scalar.to_bytes())
+    }
+
+    pub fn list<T>(&mut self, items: &[T], mut write_item: impl FnMut(&mut Self, &T)) {
+        self.len(items.len());
+        for item in items {
+            write_item(self, item)
+        }
+    }
+
+    pub fn finish(self) -> Vec<u8> {
+        self.0
+    }
+}
+
+pub(crate) struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    pub fn new(bytes: &'a [u8]) -> Self {
+        Self(bytes)
+    }
+
+    pub fn array<const N: usize>(&mut self) -> anyhow::Result<[u8; N]> {
+        if self.0.len() < N {
+            return Err(anyhow!(
+                "unexpected end of input: needed {} more bytes but only {} remain",
+                N,
+                self.0.len()
+            ));
+        }
+        let (head, tail) = self.0.split_at(N);
+        self.0 = tail;
+        Ok(head.try_into().unwrap())
+    }
+
+    pub fn u8(&mut self) -> anyhow::Result<u8> {
+        Ok(self.array::<1>()?[0])
+    }
+
+    pub fn bool(&mut self) -> anyhow::Result<bool> {
+        match self.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            byte => Err(anyhow!("{} is not a valid boolean", byte)),
+        }
+    }
+
+    pub fn u16(&mut self) -> anyhow::Result<u16> {
+        Ok(u16::from_be_bytes(self.array()?))
+    }
+
+    pub fn u32(&mut self) -> anyhow::Result<u32> {
+        Ok(u32::from_be_bytes(self.array()?))
+    }
+
+    pub fn u64(&mut self) -> anyhow::Result<u64> {
+        Ok(u64::from_be_bytes(self.array()?))
+    }
+
+    pub fn len(&mut self) -> anyhow::Result<usize> {
+        Ok(self.u32()? as usize)
+    }
+
+    pub fn str(&mut self) -> anyhow::Result<String> {
+        let len = self.len()?;
+        if self.0.len() < len {
+            return Err(anyhow!(
+                "unexpected end of input: needed {} more bytes but only {} remain",
+                len,
+                self.0.len()
+            ));
+        }
+        let (head, tail) = self.0.split_at(len);
+        self.0 = tail;
+        String::from_utf8(head.to_vec()).map_err(|_| anyhow!("string was not valid utf-8"))
+    }
+
+    pub fn g1(&mut self) -> anyhow::Result<G1Affine> {
+        Option::from(G1Affine::from_compressed(&self.array()?))
+            .ok_or_else(|| anyhow!("invalid G1 point"))
+    }
+
+    pub fn g2(&mut self) -> anyhow::Result<G2Affine> {
+        Option::from(G2Affine::from_compressed(&self.array()?))
+            .ok_or_else(|| anyhow!("invalid G2 point"))
+    }
+
+    pub fn gt(&mut self) -> anyhow::Result<Gt> {
+        Option::from(Gt::from_compressed(&self.array()?))
+            .ok_or_else(|| anyhow!("invalid Gt element"))
+    }
+
+    pub fn scalar(&mut self) -> anyhow::Result<Scalar> {
+        Option::from(Scalar::from_bytes(&self.array()?))
+            .ok_or_else(|| anyhow!("invalid bls12-381 scalar"))
+    }
+
+    pub fn point(&mut self) -> anyhow::Result<Point> {
+        Point::from_bytes(self.array()?).ok_or_else(|| anyhow!("invalid secp256k1 point"))
+    }
+
+    pub fn chain_scalar(&mut self) -> anyhow::Result<ChainScalar<Public, Zero>> {
+        ChainScalar::from_bytes(self.array()?)
+            .map(|scalar| scalar.mark::<Public>())
+            .ok_or_else(|| anyhow!("invalid secp256k1 scalar"))
+    }
+
+    pub fn list<T>(
+        &mut self,
+        mut read_item: impl FnMut(&mut Self) -> anyhow::Result<T>,
+    ) -> anyhow::Result<Vec<T>> {
+        let len = self.len()?;
+        // don't trust the length prefix for allocation since every item takes at least a byte
+        let mut items = Vec::with_capacity(len.min(self.0.len()));
+        for _ in 0..len {
+            items.push(read_item(self)?);
+        }
+        Ok(items)
+    }
+
+    /// Takes all of the remaining input.
+    pub fn rest(&mut self) -> &'a [u8] {
+        core::mem::take(&mut self.0)
+    }
+
+    pub fn finish(self) -> anyhow::Result<()> {
+        if !self.0.is_empty() {
+            return Err(anyhow!("{} trailing bytes after input", self.0.len()));
+        }
+        Ok(())
+    }
+}
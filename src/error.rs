@@ -0,0 +1,409 @@
+use core::fmt;
+
+/// The lists in the protocol messages and inputs whose length is fixed by the `Params`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ListKind {
+    /// `Message1::commits`
+    Commitments,
+    /// `Message2::openings` or `Message3::openings`
+    Openings,
+    /// `Message2::bucket_mapping`
+    BucketMapping,
+    /// The secrets Alice is encrypting (one per outcome)
+    Secrets,
+    /// The images of the secrets Bob is expecting (one per outcome)
+    OutcomeImages,
+    /// `Message3::encryptions`
+    Encryptions,
+    /// `Message3::polys`
+    Polys,
+    /// A single polynomial in `Message3::polys`
+    Poly { outcome_index: usize },
+    /// `Message3::bit_map_images`
+    BitMapImages,
+    /// The bit map images for a particular oracle
+    OracleBitMapImages { oracle_index: usize },
+    /// The bit map images for a particular digit of a particular oracle
+    DigitValueBitMapImages {
+        oracle_index: usize,
+        digit_index: usize,
+    },
+    /// The encryptions Bob kept for a particular value of a digit of a particular oracle
+    Bucket {
+        oracle_index: usize,
+        digit_index: usize,
+        digit_value: usize,
+    },
+    /// `Message3::secret_share_pads_by_oracle`
+    SecretSharePads,
+    /// The secret share pads for a particular oracle
+    OracleSecretSharePads { oracle_index: usize },
+}
+
+/// Everything that can go wrong while running the protocol.
+///
+/// Apart from [`ProtocolError::NotEnoughShares`] every variant means the counterparty (or an
+/// oracle) sent something invalid.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProtocolError {
+    /// A list didn't have the length that the `Params` require.
+    WrongLength {
+        list: ListKind,
+        expected: usize,
+        got: usize,
+    },
+    /// Bob asked Alice to open a commitment that doesn't exist.
+    OpeningOutOfRange {
+        commitment_index: usize,
+        n_commitments: usize,
+    },
+    /// Bob mapped a bucket position to a commitment that doesn't exist.
+    BucketMappingOutOfRange {
+        bucket_index: usize,
+        mapped_to: usize,
+        n_closed: usize,
+    },
+    /// The opening of a commitment didn't match its G1 part.
+    InvalidOpening { commitment_index: usize },
+    /// The scalar decrypted from an opened commitment didn't match the commitment's `R`.
+    InvalidOpeningChainPoint { commitment_index: usize },
+    /// The proof that the encryption to the anticipated attestation matches the commitment was
+    /// invalid.
+    InvalidEncryptionProof {
+        commitment_index: usize,
+        oracle_index: usize,
+        digit_index: usize,
+    },
+    /// The padded bit map secret in an encryption didn't match the commitment and the bit map
+    /// image.
+    InvalidPaddedBitMapSecret {
+        commitment_index: usize,
+        oracle_index: usize,
+        digit_index: usize,
+    },
+    /// The pad of an oracle's secret share for an outcome didn't match the image of the share.
+    InvalidOutcomePad {
+        oracle_index: usize,
+        outcome_index: usize,
+    },
+    /// An outcome index at or beyond `n_outcomes`.
+    OutcomeOutOfRange { outcome_index: u32, n_outcomes: u32 },
+    /// Fewer than `threshold` oracles gave us a share we could decrypt and verify.
+    NotEnoughShares {
+        got: usize,
+        threshold: usize,
+        rejected: Vec<RejectedShare>,
+    },
+    /// Interpolating the shares gave a secret that didn't match the outcome image.
+    WrongSecretRecovered { outcome_index: u32 },
+}
+
+impl fmt::Display for ListKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ListKind::Commitments => write!(f, "commitments"),
+            ListKind::Openings => write!(f, "openings"),
+            ListKind::BucketMapping => write!(f, "bucket mapping"),
+            ListKind::Secrets => write!(f, "secrets"),
+            ListKind::OutcomeImages => write!(f, "outcome images"),
+            ListKind::Encryptions => write!(f, "encryptions"),
+            ListKind::Polys => write!(f, "polynomials"),
+            ListKind::Poly { outcome_index } => {
+                write!(f, "polynomial for outcome {}", outcome_index)
+            }
+            ListKind::BitMapImages => write!(f, "bit map images"),
+            ListKind::OracleBitMapImages { oracle_index } => {
+                write!(f, "bit map images for oracle {}", oracle_index)
+            }
+            ListKind::DigitValueBitMapImages {
+                oracle_index,
+                digit_index,
+            } => write!(
+                f,
+                "bit map images for digit {} of oracle {}",
+                digit_index, oracle_index
+            ),
+            ListKind::Bucket {
+                oracle_index,
+                digit_index,
+                digit_value,
+            } => write!(
+                f,
+                "encryptions of value {} of digit {} of oracle {}",
+                digit_value, digit_index, oracle_index
+            ),
+            ListKind::SecretSharePads => write!(f, "secret share pads"),
+            ListKind::OracleSecretSharePads { oracle_index } => {
+                write!(f, "secret share pads for oracle {}", oracle_index)
+            }
+        }
+    }
+}
+
+impl fmt::Display for ProtocolError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProtocolError::WrongLength {
+                list,
+                expected,
+                got,
+            } => write!(f, "expected {} {} but got {}", expected, list, got),
+            ProtocolError::OpeningOutOfRange {
+                commitment_index,
+                n_commitments,
+            } => write!(
+                f,
+                "opening requested for commitment {} which is outside of range 0..{}",
+                commitment_index, n_commitments
+            ),
+            ProtocolError::BucketMappingOutOfRange {
+                bucket_index,
+                mapped_to,
+                n_closed,
+            } => write!(
+                f,
+                "bucket {} was mapped to {} which is outside of range 0..{}",
+                bucket_index, mapped_to, n_closed
+            ),
+            ProtocolError::InvalidOpening { commitment_index } => {
+                write!(f, "decommitment of commitment {} was wrong", commitment_index)
+            }
+            ProtocolError::InvalidOpeningChainPoint { commitment_index } => write!(
+                f,
+                "decommitment of chain scalar in commitment {} didn't match chain point",
+                commitment_index
+            ),
+            ProtocolError::InvalidEncryptionProof {
+                commitment_index,
+                oracle_index,
+                digit_index,
+            } => write!(
+                f,
+                "proof of equality between ciphertext and commitment {} (oracle {} digit {}) was invalid",
+                commitment_index, oracle_index, digit_index
+            ),
+            ProtocolError::InvalidPaddedBitMapSecret {
+                commitment_index,
+                oracle_index,
+                digit_index,
+            } => write!(
+                f,
+                "padded bit map secret for commitment {} (oracle {} digit {}) wasn't valid",
+                commitment_index, oracle_index, digit_index
+            ),
+            ProtocolError::InvalidOutcomePad {
+                oracle_index,
+                outcome_index,
+            } => write!(
+                f,
+                "outcome pad for outcome {} and oracle {} was wrong",
+                outcome_index, oracle_index
+            ),
+            ProtocolError::OutcomeOutOfRange {
+                outcome_index,
+                n_outcomes,
+            } => write!(
+                f,
+                "outcome {} is outside of range 0..{}",
+                outcome_index, n_outcomes
+            ),
+            ProtocolError::NotEnoughShares {
+                got,
+                threshold,
+                rejected,
+            } => {
+                write!(
+                    f,
+                    "not enough shares to reconstruct secret: got {} needed {}",
+                    got, threshold
+                )?;
+                for rejected in rejected {
+                    write!(f, "; {}", rejected)?;
+                }
+                Ok(())
+            }
+            ProtocolError::WrongSecretRecovered { outcome_index } => write!(
+                f,
+                "the secret we recovered for outcome {} was wrong",
+                outcome_index
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ProtocolError {}
+
+/// Why `Bob2` couldn't use an attestation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShareRejection {
+    /// The attestation was from a key that isn't one of the contract's oracles.
+    UnknownOracle,
+    /// An earlier attestation from the same oracle was already used.
+    DuplicateAttestation,
+    /// The attestation didn't have a signature for each digit of the outcome.
+    WrongSignatureCount { expected: usize, got: usize },
+    /// The oracle attested to a different event or outcome.
+    WrongOutcome { outcome_index: u32 },
+    /// The attestation's signatures were invalid.
+    InvalidAttestation,
+    /// None of the encryptions in the bucket for a digit decrypted to the bit map secret.
+    DecryptionFailed { digit_index: usize },
+    /// The decrypted share didn't match its image.
+    WrongShare,
+}
+
+/// An attestation `Bob2` couldn't use and why.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RejectedShare {
+    /// The attestation's position in the list we were given.
+    pub attestation_index: usize,
+    /// The oracle that made the attestation (`None` if it wasn't one of the contract's oracles).
+    pub oracle_index: Option<usize>,
+    pub reason: ShareRejection,
+}
+
+impl fmt::Display for RejectedShare {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "attestation {} ", self.attestation_index)?;
+        if let Some(oracle_index) = self.oracle_index {
+            write!(f, "from oracle {} ", oracle_index)?;
+        }
+        write!(f, "was rejected: ")?;
+        match self.reason {
+            ShareRejection::UnknownOracle => {
+                write!(f, "it was from a key that isn't one of the oracles")
+            }
+            ShareRejection::DuplicateAttestation => {
+                write!(f, "an earlier attestation from the same oracle was used")
+            }
+            ShareRejection::WrongSignatureCount { expected, got } => {
+                write!(f, "it had {} digit signatures instead of {}", got, expected)
+            }
+            ShareRejection::WrongOutcome { outcome_index } => write!(
+                f,
+                "it attested to outcome {} of a different event or a different outcome",
+                outcome_index
+            ),
+            ShareRejection::InvalidAttestation => write!(f, "its attestation was invalid"),
+            ShareRejection::DecryptionFailed { digit_index } => {
+                write!(
+                    f,
+                    "couldn't decrypt the bit map secret for digit {}",
+                    digit_index
+                )
+            }
+            ShareRejection::WrongShare => write!(f, "the decrypted share didn't match its image"),
+        }
+    }
+}
+
+/// Why a set of [`Params`](crate::common::Params) was rejected.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParamsError {
+    /// There has to be at least one oracle.
+    NoOracles,
+    /// The threshold has to be between 1 and the number of oracles.
+    InvalidThreshold { threshold: u16, n_oracles: usize },
+    /// An oracle's public key was the identity or wasn't in the prime order subgroup.
+    InvalidOracleKey { oracle_index: usize },
+    /// An oracle's proof of possession of its secret key was invalid.
+    InvalidProofOfPossession { oracle_index: usize },
+    /// An oracle's public key was the same as an earlier oracle's.
+    DuplicateOracleKey { oracle_index: usize },
+    /// There has to be at least one outcome.
+    NoOutcomes,
+    /// A cost weight was zero, negative or not finite.
+    InvalidCostWeight { weight: &'static str, value: f64 },
+    /// No cut-and-choose with buckets small enough to fit in a `u8` gives this many bits of
+    /// soundness.
+    UnreachableSecurityParam { security_param: u8 },
+    /// Neither a security parameter nor explicit cut-and-choose parameters were given.
+    MissingSecurityParam,
+    /// The proportion of commitments left closed has to be less than 1 and can't be so small that
+    /// more than [`MAX_OPENINGS_PER_CLOSED`] are opened for each closed one.
+    ///
+    /// [`MAX_OPENINGS_PER_CLOSED`]: crate::soundness::MAX_OPENINGS_PER_CLOSED
+    InvalidClosedProportion { closed_proportion: f64 },
+    /// Buckets have to contain at least one encryption.
+    ZeroBucketSize,
+    /// Outcomes have to be written in a base of at least 2.
+    InvalidBase { base: u8 },
+    /// The ElGamal base wasn't the one derived from the oracle keys and event id.
+    ElGamalBaseMismatch,
+    /// An oracle's announcement had an invalid signature.
+    InvalidAnnouncement { oracle_index: usize },
+    /// An oracle announced a different event (or number of outcomes, base or attestation time) to
+    /// the first oracle.
+    AnnouncementMismatch { oracle_index: usize },
+}
+
+impl fmt::Display for ParamsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParamsError::NoOracles => write!(f, "there must be at least one oracle"),
+            ParamsError::InvalidThreshold {
+                threshold,
+                n_oracles,
+            } => write!(
+                f,
+                "threshold {} is outside of range 1..={}",
+                threshold, n_oracles
+            ),
+            ParamsError::InvalidOracleKey { oracle_index } => write!(
+                f,
+                "the public key of oracle {} is not a valid non-identity point",
+                oracle_index
+            ),
+            ParamsError::InvalidProofOfPossession { oracle_index } => write!(
+                f,
+                "the proof of possession for oracle {}'s key was invalid",
+                oracle_index
+            ),
+            ParamsError::DuplicateOracleKey { oracle_index } => write!(
+                f,
+                "oracle {} has the same public key as an earlier oracle",
+                oracle_index
+            ),
+            ParamsError::NoOutcomes => write!(f, "there must be at least one outcome"),
+            ParamsError::InvalidCostWeight { weight, value } => write!(
+                f,
+                "cost weight `{}` is {} but must be positive and finite",
+                weight, value
+            ),
+            ParamsError::UnreachableSecurityParam { security_param } => write!(
+                f,
+                "no cut-and-choose with buckets of at most {} gives {} bits of soundness",
+                u8::MAX,
+                security_param
+            ),
+            ParamsError::MissingSecurityParam => write!(
+                f,
+                "either a security parameter or the cut-and-choose parameters must be given"
+            ),
+            ParamsError::InvalidClosedProportion { closed_proportion } => write!(
+                f,
+                "closed proportion {} is not in [1/{}, 1)",
+                closed_proportion,
+                crate::soundness::MAX_OPENINGS_PER_CLOSED + 1
+            ),
+            ParamsError::ZeroBucketSize => write!(f, "bucket size must be at least 1"),
+            ParamsError::InvalidBase { base } => write!(f, "base {} is less than 2", base),
+            ParamsError::ElGamalBaseMismatch => write!(
+                f,
+                "ElGamal base was not derived from the oracle keys and event id"
+            ),
+            ParamsError::InvalidAnnouncement { oracle_index } => write!(
+                f,
+                "the announcement from oracle {} had an invalid signature",
+                oracle_index
+            ),
+            ParamsError::AnnouncementMismatch { oracle_index } => write!(
+                f,
+                "the announcement from oracle {} doesn't match the other oracles' announcements",
+                oracle_index
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ParamsError {}
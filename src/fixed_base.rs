@@ -0,0 +1,130 @@
+//! Precomputed tables for raising a fixed Gt element to secret exponents.
+//!
+//! The exponent is written in signed base-`2^w` digits `dᵢ ∈ [-2^(w-1), 2^(w-1)]` and the table
+//! holds `j·2^(w·i)·B` for every window `i` and `j ∈ 1..=2^(w-1)` so `x·B = Σ dᵢ·2^(w·i)·B` is one
+//! table lookup and one Fp12 multiplication per window with no squarings at all. Negative digits
+//! are handled by negating the looked up element which in Gt is just conjugation.
+//!
+//! Lookups scan the whole row with [`ConditionallySelectable`] and the digits are computed without
+//! branching so the time taken doesn't depend on the exponent.
+use bls12_381::{Gt, Scalar};
+use group::Group;
+use std::sync::OnceLock;
+use subtle::{ConditionallySelectable, ConstantTimeEq};
+
+#[derive(Clone, Debug)]
+pub(crate) struct GtTable {
+    window_width: usize,
+    rows: Vec<Vec<Gt>>,
+}
+
+impl GtTable {
+    /// Builds a table for `base` with a window size suited to exponentiating it about `n_uses`
+    /// times.
+    pub(crate) fn new(base: Gt, n_uses: usize) -> Self {
+        Self::with_window_width(base, window_width_for(n_uses))
+    }
+
+    pub(crate) fn with_window_width(base: Gt, window_width: usize) -> Self {
+        assert!((2..=8).contains(&window_width));
+        let row_len = 1 << (window_width - 1);
+        let mut window_base = base;
+        let rows = (0..n_windows(window_width))
+            .map(|_| {
+                let mut row = Vec::with_capacity(row_len);
+                row.push(window_base);
+                for j in 1..row_len {
+                    row.push(row[j - 1] + window_base);
+                }
+                // 2^(w-1)·B + 2^(w-1)·B = 2^w·B is the base of the next window
+                window_base = row[row_len - 1].double();
+                row
+            })
+            .collect();
+        GtTable { window_width, rows }
+    }
+
+    /// `exponent·B` in constant time.
+    pub(crate) fn mul(&self, exponent: &Scalar) -> Gt {
+        let digits = signed_digits(exponent, self.window_width);
+        let mut acc = Gt::identity();
+        for (row, digit) in self.rows.iter().zip(digits) {
+            let is_negative = ((digit >> 15) & 1) as u8;
+            // |digit| without branching
+            let magnitude = ((digit ^ -(is_negative as i16)) + is_negative as i16) as u16;
+            let mut element = Gt::identity();
+            for (j, entry) in row.iter().enumerate() {
+                element.conditional_assign(entry, magnitude.ct_eq(&(j as u16 + 1)));
+            }
+            element.conditional_assign(&-element, is_negative.into());
+            acc += element;
+        }
+        acc
+    }
+}
+
+/// A table for the generator of Gt, built the first time it's needed.
+pub(crate) fn gt_generator_table() -> &'static GtTable {
+    static TABLE: OnceLock<GtTable> = OnceLock::new();
+    TABLE.get_or_init(|| GtTable::with_window_width(Gt::generator(), 7))
+}
+
+/// Enough windows to hold any scalar after recoding. Scalars are less than 2^255 but recoding can
+/// carry out of the top window which then needs one more.
+fn n_windows(window_width: usize) -> usize {
+    256 / window_width + 1
+}
+
+/// Picks the window width that minimizes the cost of building the table plus `n_uses`
+/// exponentiations. Both are counted in Fp12 multiplications with a constant-time lookup costing
+/// about a fiftieth of one for each entry in a row.
+fn window_width_for(n_uses: usize) -> usize {
+    let cost = |window_width: usize| {
+        let row_len = (1 << (window_width - 1)) as f64;
+        let n_windows = n_windows(window_width) as f64;
+        n_windows * row_len + n_uses as f64 * n_windows * (1.0 + row_len / 50.0)
+    };
+    (2..=8)
+        .min_by(|a, b| cost(*a).total_cmp(&cost(*b)))
+        .unwrap()
+}
+
+/// Recodes `scalar` into signed digits in `[-2^(w-1), 2^(w-1)]`, least significant first.
+fn signed_digits(scalar: &Scalar, window_width: usize) -> Vec<i16> {
+    let bytes = scalar.to_bytes();
+    let mut carry = 0i16;
+    (0..n_windows(window_width))
+        .map(|window| {
+            let digit = crate::msm::window_digit(&bytes, window * window_width, window_width)
+                as i16
+                + carry;
+            // 1 when digit >= 2^(w-1)
+            carry = (digit + (1 << (window_width - 1))) >> window_width;
+            digit - (carry << window_width)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use ff::Field;
+
+    #[test]
+    fn table_matches_double_and_add() {
+        let rng = &mut rand::thread_rng();
+        let base = Gt::random(&mut *rng);
+        let mut exponents = vec![Scalar::zero(), Scalar::one(), -Scalar::one()];
+        exponents.extend((0..3).map(|_| Scalar::random(&mut *rng)));
+        for window_width in [2, 4, 5, 8] {
+            let table = GtTable::with_window_width(base, window_width);
+            for exponent in &exponents {
+                assert_eq!(table.mul(exponent), base * exponent);
+            }
+        }
+        assert_eq!(
+            gt_generator_table().mul(&exponents[3]),
+            Gt::generator() * exponents[3]
+        );
+    }
+}
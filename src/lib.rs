@@ -1,8 +1,16 @@
 #![allow(non_snake_case)]
 pub mod alice;
+pub mod bench;
 pub mod bob;
 pub mod common;
+mod cyclotomic;
 pub mod dleq;
+mod encoding;
+pub mod error;
+mod fixed_base;
 pub mod messages;
+mod msm;
 pub mod oracle;
+mod parallel;
 pub mod poly;
+pub mod soundness;
@@ -1,29 +1,48 @@
-use crate::poly::PointPoly;
+use crate::{
+    dleq::{self, BATCHABLE_PROOF_LEN, COMPACT_PROOF_LEN},
+    encoding::{read_header, write_header, Reader, Writer},
+    poly::PointPoly,
+};
+use anyhow::anyhow;
 use bls12_381::{G1Affine, Gt, Scalar};
-use group::GroupEncoding;
 use secp256kfun::{marker::*, Point, Scalar as ChainScalar};
 use serde::Serialize;
 use std::collections::BTreeSet;
 
-#[derive(Debug, Clone)]
+/// The version of the wire format. It is the first byte of every encoded message.
+pub const WIRE_VERSION: u8 = 1;
+
+pub(crate) const MESSAGE1_TAG: u8 = 1;
+pub(crate) const MESSAGE2_TAG: u8 = 2;
+pub(crate) const MESSAGE3_TAG: u8 = 3;
+pub(crate) const NON_INTERACTIVE_TAG: u8 = 4;
+pub(crate) const PARAMS_TAG: u8 = 5;
+pub(crate) const ANNOUNCEMENT_TAG: u8 = 6;
+pub(crate) const ATTESTATION_TAG: u8 = 7;
+pub(crate) const FRAUD_PROOF_TAG: u8 = 8;
+pub(crate) const BOB2_TAG: u8 = 9;
+pub(crate) const ALICE1_TAG: u8 = 10;
+pub(crate) const ENCRYPTED_ALICE1_TAG: u8 = 11;
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct Message1 {
     pub commits: Vec<Commit>,
 }
 
-#[derive(Debug, Clone, Default, Copy)]
+#[derive(Debug, Clone, Default, Copy, PartialEq)]
 pub struct Commit {
     pub C: (G1Affine, Gt),
     pub R: Point,
     pub pad: [u8; 32],
 }
 
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Serialize, PartialEq)]
 pub struct Message2 {
     pub bucket_mapping: Vec<usize>,
     pub openings: BTreeSet<usize>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct Message3 {
     pub encryptions: Vec<(crate::dleq::Proof, Gt, ChainScalar<Public, Zero>)>,
     pub polys: Vec<PointPoly>,
@@ -33,49 +52,276 @@ pub struct Message3 {
     pub secret_share_pads_by_oracle: Vec<Vec<ChainScalar<Public, Zero>>>,
 }
 
-pub trait EstimateSize {
-    fn estimate_size(&self) -> usize;
+/// Everything Alice sends in the non-interactive mode. `Message2` is not sent since Bob derives
+/// it from `message1` (see [`Bob1::gen_message2_non_interactive`]).
+///
+/// [`Bob1::gen_message2_non_interactive`]: crate::bob::Bob1::gen_message2_non_interactive
+#[derive(Debug, Clone, PartialEq)]
+pub struct NonInteractiveMessage {
+    pub message1: Message1,
+    pub message3: Message3,
 }
 
-impl EstimateSize for Message1 {
-    fn estimate_size(&self) -> usize {
-        self.commits.len() * {
-            let c = &self.commits[0];
-            c.C.0.to_bytes().as_ref().len()
-                + c.C.1.to_compressed().as_ref().len()
-                + c.R.to_bytes().len()
-                + c.pad.len()
+impl Commit {
+    pub(crate) fn write(&self, writer: &mut Writer) {
+        writer.g1(&self.C.0);
+        writer.gt(&self.C.1);
+        writer.point(&self.R);
+        writer.bytes(&self.pad);
+    }
+
+    pub(crate) fn read(reader: &mut Reader) -> anyhow::Result<Self> {
+        Ok(Commit {
+            C: (reader.g1()?, reader.gt()?),
+            R: reader.point()?,
+            pad: reader.array()?,
+        })
+    }
+}
+
+impl Message1 {
+    pub fn encode(&self) -> Vec<u8> {
+        let mut writer = Writer::default();
+        write_header(&mut writer, MESSAGE1_TAG);
+        self.write(&mut writer);
+        writer.finish()
+    }
+
+    pub fn decode(bytes: &[u8]) -> anyhow::Result<Self> {
+        let mut reader = Reader::new(bytes);
+        read_header(&mut reader, MESSAGE1_TAG)?;
+        let message = Self::read(&mut reader)?;
+        reader.finish()?;
+        Ok(message)
+    }
+
+    fn write(&self, writer: &mut Writer) {
+        writer.list(&self.commits, |writer, commit| commit.write(writer));
+    }
+
+    fn read(reader: &mut Reader) -> anyhow::Result<Self> {
+        Ok(Message1 {
+            commits: reader.list(Commit::read)?,
+        })
+    }
+}
+
+impl Message2 {
+    pub fn encode(&self) -> Vec<u8> {
+        let mut writer = Writer::default();
+        write_header(&mut writer, MESSAGE2_TAG);
+        writer.list(&self.bucket_mapping, |writer, from| writer.len(*from));
+        // BTreeSet iterates in ascending order which makes the encoding canonical
+        let openings = self.openings.iter().cloned().collect::<Vec<_>>();
+        writer.list(&openings, |writer, opening| writer.len(*opening));
+        writer.finish()
+    }
+
+    pub fn decode(bytes: &[u8]) -> anyhow::Result<Self> {
+        let mut reader = Reader::new(bytes);
+        read_header(&mut reader, MESSAGE2_TAG)?;
+        let bucket_mapping = reader.list(|reader| reader.len())?;
+        let openings_list = reader.list(|reader| reader.len())?;
+        reader.finish()?;
+        if openings_list.windows(2).any(|pair| pair[0] >= pair[1]) {
+            return Err(anyhow!("openings must be strictly increasing"));
         }
+        Ok(Message2 {
+            bucket_mapping,
+            openings: openings_list.into_iter().collect(),
+        })
+    }
+}
+
+impl Message3 {
+    pub fn encode(&self) -> Vec<u8> {
+        let mut writer = Writer::default();
+        write_header(&mut writer, MESSAGE3_TAG);
+        self.write(&mut writer);
+        writer.finish()
+    }
+
+    pub fn decode(bytes: &[u8]) -> anyhow::Result<Self> {
+        let mut reader = Reader::new(bytes);
+        read_header(&mut reader, MESSAGE3_TAG)?;
+        let message = Self::read(&mut reader)?;
+        reader.finish()?;
+        Ok(message)
+    }
+
+    fn write(&self, writer: &mut Writer) {
+        writer.list(
+            &self.encryptions,
+            |writer, (proof, encryption, padded_secret)| {
+                writer.bool(matches!(proof, dleq::Proof::Batchable { .. }));
+                writer.bytes(&dleq::proof_to_bytes(proof));
+                writer.gt(encryption);
+                writer.chain_scalar(padded_secret);
+            },
+        );
+        writer.list(&self.polys, |writer, poly| {
+            writer.list(poly.points(), |writer, point| writer.point(point))
+        });
+        writer.list(&self.openings, |writer, opening| writer.scalar(opening));
+        writer.list(&self.bit_map_images, |writer, oracle_images| {
+            writer.list(oracle_images, |writer, bit_images| {
+                writer.list(bit_images, |writer, image| writer.point(image))
+            })
+        });
+        writer.list(&self.secret_share_pads_by_oracle, |writer, pads| {
+            writer.list(pads, |writer, pad| writer.chain_scalar(pad))
+        });
+    }
+
+    fn read(reader: &mut Reader) -> anyhow::Result<Self> {
+        let encryptions = reader.list(|reader| {
+            let proof = if reader.bool()? {
+                dleq::batchable_proof_from_bytes(&reader.array::<BATCHABLE_PROOF_LEN>()?)
+            } else {
+                dleq::compact_proof_from_bytes(&reader.array::<COMPACT_PROOF_LEN>()?)
+            }
+            .ok_or_else(|| anyhow!("invalid DLEQ proof"))?;
+            Ok((proof, reader.gt()?, reader.chain_scalar()?))
+        })?;
+        let polys = reader.list(|reader| Ok(PointPoly::new(reader.list(Reader::point)?)))?;
+        let openings = reader.list(Reader::scalar)?;
+        let bit_map_images =
+            reader.list(|reader| reader.list(|reader| reader.list(Reader::point)))?;
+        let secret_share_pads_by_oracle =
+            reader.list(|reader| reader.list(Reader::chain_scalar))?;
+        Ok(Message3 {
+            encryptions,
+            polys,
+            openings,
+            bit_map_images,
+            secret_share_pads_by_oracle,
+        })
     }
 }
 
-impl EstimateSize for Message2 {
-    fn estimate_size(&self) -> usize {
-        bincode::serde::encode_to_vec(&self, bincode::config::standard())
-            .unwrap()
-            .len()
+impl NonInteractiveMessage {
+    pub fn encode(&self) -> Vec<u8> {
+        let mut writer = Writer::default();
+        write_header(&mut writer, NON_INTERACTIVE_TAG);
+        self.message1.write(&mut writer);
+        self.message3.write(&mut writer);
+        writer.finish()
+    }
+
+    pub fn decode(bytes: &[u8]) -> anyhow::Result<Self> {
+        let mut reader = Reader::new(bytes);
+        read_header(&mut reader, NON_INTERACTIVE_TAG)?;
+        let message1 = Message1::read(&mut reader)?;
+        let message3 = Message3::read(&mut reader)?;
+        reader.finish()?;
+        Ok(NonInteractiveMessage { message1, message3 })
     }
 }
 
-impl EstimateSize for Message3 {
-    fn estimate_size(&self) -> usize {
-        self.encryptions.len() * {
-            let (_proof, gt, scalar) = &self.encryptions[0];
-            32 + 32 // proof size
-                + gt.to_compressed().as_ref().len()
-                + scalar.to_bytes().len()
-        } + bincode::serde::encode_to_vec(&self.polys, bincode::config::standard())
-            .unwrap()
-            .len()
-            + self.openings.len() * 32
-            + bincode::serde::encode_to_vec(
-                &self.secret_share_pads_by_oracle,
-                bincode::config::standard(),
-            )
-            .unwrap()
-            .len()
-            + bincode::serde::encode_to_vec(&self.bit_map_images, bincode::config::standard())
-                .unwrap()
-                .len()
+#[cfg(test)]
+mod test {
+    use super::*;
+    use bls12_381::G1Projective;
+    use ff::Field;
+    use group::Group;
+    use rand::{CryptoRng, RngCore};
+
+    fn random_commit(rng: &mut (impl RngCore + CryptoRng)) -> Commit {
+        let mut pad = [0u8; 32];
+        rng.fill_bytes(&mut pad);
+        Commit {
+            C: (
+                G1Affine::from(bls12_381::G1Projective::random(&mut *rng)),
+                Gt::random(&mut *rng),
+            ),
+            R: Point::random(rng),
+            pad,
+        }
+    }
+
+    fn random_chain_scalar() -> ChainScalar<Public, Zero> {
+        ChainScalar::random(&mut rand::thread_rng())
+            .mark::<Zero>()
+            .mark::<Public>()
+    }
+
+    #[test]
+    fn message1_roundtrip() {
+        let rng = &mut rand::thread_rng();
+        let message = Message1 {
+            commits: (0..3).map(|_| random_commit(rng)).collect(),
+        };
+        let bytes = message.encode();
+        assert_eq!(bytes.len(), 2 + 4 + 3 * (48 + 288 + 33 + 32));
+        assert_eq!(Message1::decode(&bytes).unwrap(), message);
+    }
+
+    #[test]
+    fn message2_roundtrip() {
+        let message = Message2 {
+            bucket_mapping: vec![3, 0, 2, 1],
+            openings: [1, 5, 8].into_iter().collect(),
+        };
+        let bytes = message.encode();
+        assert_eq!(Message2::decode(&bytes).unwrap(), message);
+
+        let mut non_canonical = bytes.clone();
+        // swap the first two openings so they're no longer ascending
+        let openings_start = 2 + 4 + 4 * 4 + 4;
+        non_canonical[openings_start + 3] = 5;
+        non_canonical[openings_start + 7] = 1;
+        assert!(Message2::decode(&non_canonical).is_err());
+    }
+
+    #[test]
+    fn message3_roundtrip() {
+        let rng = &mut rand::thread_rng();
+        let encryptions = (0..4)
+            .map(|i| {
+                let proof = if i % 2 == 0 {
+                    dleq::Proof::Batchable {
+                        announcement: (
+                            G1Projective::random(&mut *rng).into(),
+                            Gt::random(&mut *rng),
+                        ),
+                        response: Scalar::random(&mut *rng),
+                    }
+                } else {
+                    let mut proof_bytes = [i as u8; COMPACT_PROOF_LEN];
+                    proof_bytes[31..].copy_from_slice(&Scalar::random(&mut *rng).to_bytes());
+                    dleq::compact_proof_from_bytes(&proof_bytes).unwrap()
+                };
+                (proof, Gt::random(&mut *rng), random_chain_scalar())
+            })
+            .collect();
+        let message = Message3 {
+            encryptions,
+            polys: (0..3)
+                .map(|_| PointPoly::new((0..2).map(|_| Point::random(rng)).collect()))
+                .collect(),
+            openings: (0..5).map(|_| Scalar::random(&mut *rng)).collect(),
+            bit_map_images: (0..2)
+                .map(|_| {
+                    (0..3)
+                        .map(|bit| (0..bit).map(|_| Point::random(rng)).collect())
+                        .collect()
+                })
+                .collect(),
+            secret_share_pads_by_oracle: (0..2)
+                .map(|_| (0..3).map(|_| random_chain_scalar()).collect())
+                .collect(),
+        };
+        let bytes = message.encode();
+        assert_eq!(Message3::decode(&bytes).unwrap(), message);
+        assert!(Message3::decode(&bytes[..bytes.len() - 1]).is_err());
+        let mut trailing = bytes;
+        trailing.push(0);
+        assert!(Message3::decode(&trailing).is_err());
+    }
+
+    #[test]
+    fn wrong_message_type() {
+        let message = Message1 { commits: vec![] };
+        assert!(Message2::decode(&message.encode()).is_err());
     }
 }
@@ -0,0 +1,104 @@
+//! Multi-scalar multiplication (`Σ s_i·P_i`) for the bls12-381 groups.
+//!
+//! We use Pippenger's bucket method which needs roughly `255/c · (n + 2^c)` group additions for
+//! `n` terms and window size `c` instead of the `~380·n` a naive sum takes. It's generic over
+//! [`Group`] so it works for G1 and Gt alike (in Gt "addition" is multiplication in Fp12). None of
+//! this is constant time so only use it on public values.
+use bls12_381::Scalar;
+use group::Group;
+use sha2::{digest::Digest, Sha256};
+
+/// Computes `Σ scalars[i]·points[i]`, splitting the work between threads when the `parallel`
+/// feature is enabled.
+pub(crate) fn msm<G: Group<Scalar = Scalar> + Send + Sync>(scalars: &[Scalar], points: &[G]) -> G {
+    assert_eq!(scalars.len(), points.len());
+    let chunk_size = points.len().div_ceil(crate::parallel::n_threads()).max(1);
+    crate::parallel::map(
+        scalars
+            .chunks(chunk_size)
+            .zip(points.chunks(chunk_size))
+            .collect(),
+        |(scalars, points)| pippenger(scalars, points),
+    )
+    .into_iter()
+    .sum()
+}
+
+fn pippenger<G: Group<Scalar = Scalar>>(scalars: &[Scalar], points: &[G]) -> G {
+    // the optimal window is around ln(n)
+    let c = match points.len() {
+        0 => return G::identity(),
+        1..=31 => 3,
+        n => (usize::BITS - n.leading_zeros()) as usize * 69 / 100 + 2,
+    };
+    let scalars = scalars.iter().map(Scalar::to_bytes).collect::<Vec<_>>();
+    let n_windows = 255_usize.div_ceil(c);
+
+    let mut result = G::identity();
+    for window in (0..n_windows).rev() {
+        for _ in 0..c {
+            result = result.double();
+        }
+        let mut buckets = vec![G::identity(); (1 << c) - 1];
+        for (scalar, point) in scalars.iter().zip(points) {
+            let digit = window_digit(scalar, window * c, c);
+            if digit != 0 {
+                buckets[digit - 1] += point;
+            }
+        }
+        // Σ (i+1)·buckets[i] by summing the running sums from the top bucket down
+        let mut running_sum = G::identity();
+        let mut window_sum = G::identity();
+        for bucket in buckets.into_iter().rev() {
+            running_sum += bucket;
+            window_sum += running_sum;
+        }
+        result += window_sum;
+    }
+    result
+}
+
+/// The `width` bits of the little-endian `scalar` starting at bit `start`.
+pub(crate) fn window_digit(scalar: &[u8; 32], start: usize, width: usize) -> usize {
+    (start..(start + width).min(256))
+        .map(|bit| ((scalar[bit / 8] >> (bit % 8)) & 1) as usize)
+        .enumerate()
+        .fold(0, |digit, (i, bit)| digit | (bit << i))
+}
+
+/// A 128-bit coefficient for the `index`th item of a batch. `seed` must commit to everything in the
+/// batch so the prover can't choose the items knowing the coefficients.
+pub(crate) fn batch_coefficient(seed: &[u8], index: u32) -> Scalar {
+    let hash = Sha256::default()
+        .chain(seed)
+        .chain(index.to_be_bytes())
+        .finalize();
+    let lo = u64::from_be_bytes(hash[..8].try_into().unwrap());
+    let hi = u64::from_be_bytes(hash[8..16].try_into().unwrap());
+    Scalar::from_raw([lo, hi, 0, 0])
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use bls12_381::{G1Projective, Gt};
+    use ff::Field;
+
+    #[test]
+    fn msm_matches_naive_sum() {
+        let rng = &mut rand::thread_rng();
+        for n in [0, 1, 5, 40] {
+            let scalars = (0..n)
+                .map(|_| Scalar::random(&mut *rng))
+                .collect::<Vec<_>>();
+            let g1s = (0..n)
+                .map(|_| G1Projective::random(&mut *rng))
+                .collect::<Vec<_>>();
+            let gts = (0..n).map(|_| Gt::random(&mut *rng)).collect::<Vec<_>>();
+            let naive_g1: G1Projective = scalars.iter().zip(&g1s).map(|(s, p)| p * s).sum();
+            let naive_gt: Gt = scalars.iter().zip(&gts).map(|(s, p)| p * s).sum();
+            assert_eq!(msm(&scalars, &g1s), naive_g1);
+            assert_eq!(msm(&scalars, &gts), naive_gt);
+        }
+    }
+}
@@ -1,8 +1,20 @@
-use bls12_381::{G1Affine, G2Affine, Scalar};
+use bls12_381::{
+    hash_to_curve::{ExpandMsgXmd, HashToCurve},
+    multi_miller_loop, pairing, G1Affine, G2Affine, G2Prepared, G2Projective, Gt, Scalar,
+};
 use ff::Field;
+use group::Curve;
 use rand::RngCore;
+use sha2::{digest::Digest, Sha256};
 
-use crate::common::message_for_event_index;
+use crate::{
+    common::{message_for_event_index, to_digits, verify_bls_sig, EventContext},
+    encoding::{read_header, write_header, Reader, Writer},
+    messages::{ANNOUNCEMENT_TAG, ATTESTATION_TAG, FRAUD_PROOF_TAG},
+};
+
+const ANNOUNCEMENT_DST: &[u8] = b"DLC-VENC-PAIRING-V01-ANNOUNCEMENT_XMD:SHA-256_SSWU_RO_";
+const POP_DST: &[u8] = b"DLC-VENC-PAIRING-V01-POP_XMD:SHA-256_SSWU_RO_";
 
 #[allow(dead_code)]
 pub struct Oracle {
@@ -22,21 +34,512 @@ impl Oracle {
     }
 
     pub fn public_key(&self) -> G1Affine {
-        self.pk.clone()
+        self.pk
+    }
+
+    /// Signs our own public key (under a separate domain) to show we know the secret key.
+    pub fn proof_of_possession(&self) -> G2Affine {
+        G2Affine::from(pop_message(&self.pk) * self.sk)
+    }
+
+    /// Our public key along with its proof of possession. This is what goes into
+    /// [`ParamsBuilder::new`](crate::common::ParamsBuilder::new).
+    pub fn key(&self) -> OracleKey {
+        OracleKey {
+            public_key: self.pk,
+            proof_of_possession: self.proof_of_possession(),
+        }
     }
 
+    /// Commits to attesting to the outcome of `event_id` (one of `n_outcomes`) at
+    /// `attestation_time`.
+    pub fn announce(
+        &self,
+        event_id: &str,
+        n_outcomes: u32,
+        base: u8,
+        attestation_time: u64,
+    ) -> Announcement {
+        let mut announcement = Announcement {
+            event_id: event_id.into(),
+            n_outcomes,
+            base,
+            attestation_time,
+            key: self.key(),
+            signature: G2Affine::identity(),
+        };
+        announcement.signature = G2Affine::from(announcement.signing_point() * self.sk);
+        announcement
+    }
+
+    /// Attests to `outcome_index` by signing each of its `n_outcome_digits` base-`base` digits.
     pub fn attest(
         &self,
         event_id: &str,
-        n_outcome_bits: usize,
+        base: u8,
+        n_outcome_digits: usize,
         outcome_index: u32,
-    ) -> Vec<G2Affine> {
-        (0..n_outcome_bits)
-            .map(|bit_index| {
-                let bit_value = ((outcome_index >> bit_index) & 0x01) == 1;
-                let message = message_for_event_index(event_id, bit_index as u32, bit_value);
-                G2Affine::from(&message * &self.sk)
+    ) -> Attestation {
+        let digits = to_digits(outcome_index, base, n_outcome_digits);
+        let digit_signatures = digits
+            .iter()
+            .enumerate()
+            .map(|(digit_index, digit_value)| {
+                let message =
+                    message_for_event_index(event_id, base, digit_index as u32, *digit_value);
+                G2Affine::from(message * self.sk)
             })
-            .collect()
+            .collect();
+        Attestation {
+            public_key: self.pk,
+            event_id: event_id.into(),
+            base,
+            outcome_index,
+            digit_signatures,
+        }
+    }
+}
+
+fn pop_message(public_key: &G1Affine) -> G2Projective {
+    <G2Projective as HashToCurve<ExpandMsgXmd<Sha256>>>::hash_to_curve(
+        public_key.to_compressed(),
+        POP_DST,
+    )
+}
+
+/// An oracle's public key along with a BLS signature on the key itself proving the oracle knows the
+/// secret key. Without the proof an oracle could choose its key as a function of the other oracles'
+/// keys (a rogue key attack).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OracleKey {
+    pub public_key: G1Affine,
+    pub proof_of_possession: G2Affine,
+}
+
+impl OracleKey {
+    /// Checks the public key is a point in the prime order subgroup other than the identity.
+    pub fn is_valid_public_key(&self) -> bool {
+        let key = &self.public_key;
+        bool::from(!key.is_identity() & key.is_on_curve() & key.is_torsion_free())
+    }
+
+    /// Checks the proof of possession is a signature on the public key by the public key.
+    pub fn verify_proof_of_possession(&self) -> bool {
+        let pop = &self.proof_of_possession;
+        if !bool::from(pop.is_on_curve() & pop.is_torsion_free()) {
+            return false;
+        }
+        let message = G2Prepared::from(G2Affine::from(pop_message(&self.public_key)));
+        let pop = G2Prepared::from(*pop);
+        multi_miller_loop(&[
+            (&self.public_key, &message),
+            (&-G1Affine::generator(), &pop),
+        ])
+        .final_exponentiation()
+            == Gt::identity()
+    }
+}
+
+/// An oracle's signed commitment to attest to an event.
+///
+/// Contracts should be built from announcements with [`ParamsBuilder::from_announcements`] so the
+/// event id and outcome encoding are the ones the oracles actually agreed to.
+///
+/// [`ParamsBuilder::from_announcements`]: crate::common::ParamsBuilder::from_announcements
+#[derive(Debug, Clone, PartialEq)]
+pub struct Announcement {
+    pub event_id: String,
+    /// The outcome will be attested to as an index in `0..n_outcomes`.
+    pub n_outcomes: u32,
+    /// The base the outcome index will be written in, with a signature on each digit (see
+    /// [`crate::common::compute_optimal_base`] for choosing one).
+    pub base: u8,
+    /// When the oracle will attest (seconds since the unix epoch).
+    pub attestation_time: u64,
+    pub key: OracleKey,
+    /// A BLS signature by `key` on everything above.
+    pub signature: G2Affine,
+}
+
+impl Announcement {
+    fn write_body(&self, writer: &mut Writer) {
+        writer.str(&self.event_id);
+        writer.u32(self.n_outcomes);
+        writer.u8(self.base);
+        writer.u64(self.attestation_time);
+        writer.g1(&self.key.public_key);
+        writer.g2(&self.key.proof_of_possession);
+    }
+
+    fn signing_point(&self) -> G2Projective {
+        let mut writer = Writer::default();
+        write_header(&mut writer, ANNOUNCEMENT_TAG);
+        self.write_body(&mut writer);
+        <G2Projective as HashToCurve<ExpandMsgXmd<Sha256>>>::hash_to_curve(
+            writer.finish(),
+            ANNOUNCEMENT_DST,
+        )
+    }
+
+    /// Checks the signature is from `key`. This doesn't check the key itself (see [`OracleKey`]).
+    pub fn verify(&self) -> bool {
+        let message = G2Affine::from(self.signing_point());
+        pairing(&self.key.public_key, &message) == pairing(&G1Affine::generator(), &self.signature)
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut writer = Writer::default();
+        write_header(&mut writer, ANNOUNCEMENT_TAG);
+        self.write_body(&mut writer);
+        writer.g2(&self.signature);
+        writer.finish()
+    }
+
+    /// Decodes an announcement. This doesn't check the signature (see [`Announcement::verify`]).
+    pub fn decode(bytes: &[u8]) -> anyhow::Result<Self> {
+        let mut reader = Reader::new(bytes);
+        read_header(&mut reader, ANNOUNCEMENT_TAG)?;
+        let announcement = Announcement {
+            event_id: reader.str()?,
+            n_outcomes: reader.u32()?,
+            base: reader.u8()?,
+            attestation_time: reader.u64()?,
+            key: OracleKey {
+                public_key: reader.g1()?,
+                proof_of_possession: reader.g2()?,
+            },
+            signature: reader.g2()?,
+        };
+        reader.finish()?;
+        Ok(announcement)
+    }
+}
+
+/// An oracle's attestation to the outcome of an event: a BLS signature on each base-`base` digit of
+/// the outcome index (least significant first).
+#[derive(Debug, Clone, PartialEq)]
+pub struct Attestation {
+    pub public_key: G1Affine,
+    pub event_id: String,
+    pub base: u8,
+    pub outcome_index: u32,
+    pub digit_signatures: Vec<G2Affine>,
+}
+
+impl Attestation {
+    fn write_body(&self, writer: &mut Writer) {
+        writer.g1(&self.public_key);
+        writer.str(&self.event_id);
+        writer.u8(self.base);
+        writer.u32(self.outcome_index);
+    }
+
+    /// Checks every digit signature at once.
+    ///
+    /// Rather than checking `e(pk, H(digit_i)) == e(G1, sig_i)` for each digit we check a random
+    /// linear combination of them: `e(pk, Σ c_i·H(digit_i)) == e(G1, Σ c_i·sig_i)`, which takes a
+    /// single multi-Miller loop with two pairs and one final exponentiation. The coefficients are
+    /// 128-bit and derived by hashing the attestation so the oracle can't choose signatures that
+    /// cancel out.
+    pub fn verify(&self) -> bool {
+        self.verify_with(|digit_index, digit_value| {
+            message_for_event_index(&self.event_id, self.base, digit_index, digit_value)
+        })
+    }
+
+    /// The same as [`Attestation::verify`] but takes the messages from `context` rather than
+    /// hashing them. Returns `false` if the attestation is for a different event or doesn't have a
+    /// signature for every digit of it.
+    pub fn verify_with_context(&self, context: &EventContext) -> bool {
+        if self.event_id != context.event_id()
+            || self.base != context.base()
+            || self.digit_signatures.len() != context.n_outcome_digits() as usize
+        {
+            return false;
+        }
+        self.verify_with(|digit_index, digit_value| context.message(digit_index, digit_value))
+    }
+
+    fn verify_with(&self, message_for_digit: impl Fn(u32, u32) -> G2Affine) -> bool {
+        let n_digits = self.digit_signatures.len().min(32) as u32;
+        // with no digits both sums are the identity and the pairing check passes for anything
+        if self.digit_signatures.is_empty()
+            || self.base < 2
+            || (self.base as u64)
+                .checked_pow(n_digits)
+                .is_some_and(|n_outcomes| self.outcome_index as u64 >= n_outcomes)
+            || !bool::from(!self.public_key.is_identity() & self.public_key.is_torsion_free())
+            || self
+                .digit_signatures
+                .iter()
+                .any(|sig| !bool::from(sig.is_on_curve() & sig.is_torsion_free()))
+        {
+            return false;
+        }
+
+        let mut writer = Writer::default();
+        self.write_body(&mut writer);
+        writer.list(&self.digit_signatures, |w, sig| w.g2(sig));
+        let seed = Sha256::default()
+            .chain(b"dlc-venc-pairing/attestation-batch")
+            .chain(writer.finish())
+            .finalize();
+
+        let digits = to_digits(self.outcome_index, self.base, self.digit_signatures.len());
+        let (messages, sigs) = self.digit_signatures.iter().zip(digits).enumerate().fold(
+            (G2Projective::identity(), G2Projective::identity()),
+            |(messages, sigs), (digit_index, (sig, digit_value))| {
+                let coefficient = crate::msm::batch_coefficient(&seed, digit_index as u32);
+                let message = message_for_digit(digit_index as u32, digit_value);
+                (messages + message * coefficient, sigs + sig * coefficient)
+            },
+        );
+
+        multi_miller_loop(&[
+            (&self.public_key, &G2Prepared::from(messages.to_affine())),
+            (&-G1Affine::generator(), &G2Prepared::from(sigs.to_affine())),
+        ])
+        .final_exponentiation()
+            == Gt::identity()
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut writer = Writer::default();
+        write_header(&mut writer, ATTESTATION_TAG);
+        self.write_body(&mut writer);
+        writer.list(&self.digit_signatures, |w, sig| w.g2(sig));
+        writer.finish()
+    }
+
+    /// Decodes an attestation. This doesn't check the signatures (see [`Attestation::verify`]).
+    pub fn decode(bytes: &[u8]) -> anyhow::Result<Self> {
+        let mut reader = Reader::new(bytes);
+        read_header(&mut reader, ATTESTATION_TAG)?;
+        let attestation = Attestation {
+            public_key: reader.g1()?,
+            event_id: reader.str()?,
+            base: reader.u8()?,
+            outcome_index: reader.u32()?,
+            digit_signatures: reader.list(|r| r.g2())?,
+        };
+        reader.finish()?;
+        Ok(attestation)
+    }
+}
+
+/// Proof that an oracle signed two different values of the same digit of an event. It only needs
+/// the oracle's public key to check so anyone can be convinced the oracle equivocated.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FraudProof {
+    pub public_key: G1Affine,
+    pub event_id: String,
+    pub base: u8,
+    pub digit_index: u32,
+    pub first_value: u32,
+    pub first_signature: G2Affine,
+    pub second_value: u32,
+    pub second_signature: G2Affine,
+}
+
+impl FraudProof {
+    /// Looks for a digit that the two attestations from the same oracle for the same event sign
+    /// differently. Returns `None` unless they are both valid and attest to different outcomes.
+    pub fn from_attestations(first: &Attestation, second: &Attestation) -> Option<Self> {
+        if first.public_key != second.public_key
+            || first.event_id != second.event_id
+            || first.base != second.base
+            || first.base < 2
+            || first.outcome_index == second.outcome_index
+        {
+            return None;
+        }
+        let n_digits = first
+            .digit_signatures
+            .len()
+            .min(second.digit_signatures.len());
+        let first_digits = to_digits(first.outcome_index, first.base, n_digits);
+        let second_digits = to_digits(second.outcome_index, second.base, n_digits);
+        let digit_index = (0..n_digits).find(|i| first_digits[*i] != second_digits[*i])?;
+        let fraud_proof = FraudProof {
+            public_key: first.public_key,
+            event_id: first.event_id.clone(),
+            base: first.base,
+            digit_index: digit_index as u32,
+            first_value: first_digits[digit_index],
+            first_signature: first.digit_signatures[digit_index],
+            second_value: second_digits[digit_index],
+            second_signature: second.digit_signatures[digit_index],
+        };
+        if fraud_proof.verify() {
+            Some(fraud_proof)
+        } else {
+            None
+        }
+    }
+
+    pub fn verify(&self) -> bool {
+        // with the identity as the key the identity would be a signature on every message
+        self.first_value != self.second_value
+            && bool::from(!self.public_key.is_identity() & self.public_key.is_torsion_free())
+            && verify_bls_sig(
+                &self.public_key,
+                &self.event_id,
+                self.base,
+                self.digit_index,
+                self.first_value,
+                &self.first_signature,
+            )
+            && verify_bls_sig(
+                &self.public_key,
+                &self.event_id,
+                self.base,
+                self.digit_index,
+                self.second_value,
+                &self.second_signature,
+            )
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut writer = Writer::default();
+        write_header(&mut writer, FRAUD_PROOF_TAG);
+        writer.g1(&self.public_key);
+        writer.str(&self.event_id);
+        writer.u8(self.base);
+        writer.u32(self.digit_index);
+        writer.u32(self.first_value);
+        writer.g2(&self.first_signature);
+        writer.u32(self.second_value);
+        writer.g2(&self.second_signature);
+        writer.finish()
+    }
+
+    /// Decodes a fraud proof. This doesn't check it (see [`FraudProof::verify`]).
+    pub fn decode(bytes: &[u8]) -> anyhow::Result<Self> {
+        let mut reader = Reader::new(bytes);
+        read_header(&mut reader, FRAUD_PROOF_TAG)?;
+        let fraud_proof = FraudProof {
+            public_key: reader.g1()?,
+            event_id: reader.str()?,
+            base: reader.u8()?,
+            digit_index: reader.u32()?,
+            first_value: reader.u32()?,
+            first_signature: reader.g2()?,
+            second_value: reader.u32()?,
+            second_signature: reader.g2()?,
+        };
+        reader.finish()?;
+        Ok(fraud_proof)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn announcement_roundtrip_and_verify() {
+        let oracle = Oracle::random(&mut rand::thread_rng());
+        let announcement = oracle.announce("btc-usd-2022-06-01", 100, 2, 1_654_041_600);
+        assert!(announcement.verify());
+        let decoded = Announcement::decode(&announcement.encode()).unwrap();
+        assert_eq!(decoded, announcement);
+
+        let mut tampered = announcement.clone();
+        tampered.n_outcomes = 101;
+        assert!(!tampered.verify());
+        tampered = announcement.clone();
+        tampered.base = 3;
+        assert!(!tampered.verify());
+
+        let other = Oracle::random(&mut rand::thread_rng());
+        tampered = announcement;
+        tampered.key = other.key();
+        assert!(!tampered.verify());
+    }
+
+    #[test]
+    fn proof_of_possession() {
+        let oracle = Oracle::random(&mut rand::thread_rng());
+        let other = Oracle::random(&mut rand::thread_rng());
+        let key = oracle.key();
+        assert!(key.is_valid_public_key());
+        assert!(key.verify_proof_of_possession());
+
+        let stolen_pop = OracleKey {
+            public_key: other.public_key(),
+            proof_of_possession: key.proof_of_possession,
+        };
+        assert!(!stolen_pop.verify_proof_of_possession());
+
+        let identity = OracleKey {
+            public_key: G1Affine::identity(),
+            proof_of_possession: G2Affine::identity(),
+        };
+        assert!(!identity.is_valid_public_key());
+    }
+
+    #[test]
+    fn attestation_batch_verify() {
+        let oracle = Oracle::random(&mut rand::thread_rng());
+        let attestation = oracle.attest("event", 2, 5, 19);
+        assert!(attestation.verify());
+        assert_eq!(
+            Attestation::decode(&attestation.encode()).unwrap(),
+            attestation
+        );
+
+        let mut wrong_outcome = attestation.clone();
+        wrong_outcome.outcome_index = 18;
+        assert!(!wrong_outcome.verify());
+
+        let mut swapped = attestation.clone();
+        swapped.digit_signatures.swap(0, 1);
+        assert!(!swapped.verify());
+
+        let mut out_of_range = attestation.clone();
+        out_of_range.digit_signatures.truncate(4);
+        assert!(!out_of_range.verify());
+
+        let mut empty = attestation.clone();
+        empty.digit_signatures.clear();
+        empty.outcome_index = 0;
+        assert!(!empty.verify());
+
+        let mut lowest_dropped = attestation;
+        lowest_dropped.digit_signatures.remove(0);
+        lowest_dropped.outcome_index = 19 >> 1;
+        assert!(!lowest_dropped.verify());
+
+        let ternary = oracle.attest("event", 3, 3, 19);
+        assert!(ternary.verify());
+        let mut wrong_base = ternary;
+        wrong_base.base = 4;
+        assert!(!wrong_base.verify());
+    }
+
+    #[test]
+    fn equivocation_is_detected() {
+        let oracle = Oracle::random(&mut rand::thread_rng());
+        let first = oracle.attest("event", 2, 4, 0b0110);
+        let second = oracle.attest("event", 2, 4, 0b0011);
+        let fraud_proof = FraudProof::from_attestations(&first, &second).unwrap();
+        assert_eq!(fraud_proof.digit_index, 0);
+        assert!(fraud_proof.verify());
+        assert_eq!(
+            FraudProof::decode(&fraud_proof.encode()).unwrap(),
+            fraud_proof
+        );
+
+        assert!(FraudProof::from_attestations(&first, &first.clone()).is_none());
+        let other_event = oracle.attest("other-event", 2, 4, 0b0011);
+        assert!(FraudProof::from_attestations(&first, &other_event).is_none());
+
+        let mut framed = fraud_proof;
+        framed.public_key = Oracle::random(&mut rand::thread_rng()).public_key();
+        assert!(!framed.verify());
+        framed.public_key = G1Affine::identity();
+        framed.first_signature = G2Affine::identity();
+        framed.second_signature = G2Affine::identity();
+        assert!(!framed.verify());
     }
 }
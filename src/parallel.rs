@@ -0,0 +1,59 @@
+//! Runs the heavy loops of the protocol on every core when the `parallel` feature is enabled.
+//!
+//! Items never share an RNG. Callers draw a seed for each item up front with [`item_rngs`] so the
+//! output is the same whether or not the feature is enabled.
+use rand::{CryptoRng, RngCore, SeedableRng};
+use rand_chacha::ChaCha20Rng;
+
+#[cfg(feature = "parallel")]
+pub(crate) fn map<T: Send, U: Send>(items: Vec<T>, f: impl Fn(T) -> U + Send + Sync) -> Vec<U> {
+    use rayon::prelude::*;
+    items.into_par_iter().map(f).collect()
+}
+
+// same bounds as above so code that builds without the feature builds with it
+#[cfg(not(feature = "parallel"))]
+pub(crate) fn map<T: Send, U: Send>(items: Vec<T>, f: impl Fn(T) -> U + Send + Sync) -> Vec<U> {
+    items.into_iter().map(f).collect()
+}
+
+/// How many threads [`map`] spreads work over.
+#[cfg(feature = "parallel")]
+pub(crate) fn n_threads() -> usize {
+    rayon::current_num_threads()
+}
+
+#[cfg(not(feature = "parallel"))]
+pub(crate) fn n_threads() -> usize {
+    1
+}
+
+/// Like [`map`] but stops once an item fails. Without the feature that's the first item (in order)
+/// that fails. With it the error can be from any failing item that was started.
+#[cfg(feature = "parallel")]
+pub(crate) fn try_map<T: Send, U: Send, E: Send>(
+    items: Vec<T>,
+    f: impl Fn(T) -> Result<U, E> + Send + Sync,
+) -> Result<Vec<U>, E> {
+    use rayon::prelude::*;
+    items.into_par_iter().map(f).collect()
+}
+
+#[cfg(not(feature = "parallel"))]
+pub(crate) fn try_map<T: Send, U: Send, E: Send>(
+    items: Vec<T>,
+    f: impl Fn(T) -> Result<U, E> + Send + Sync,
+) -> Result<Vec<U>, E> {
+    items.into_iter().map(f).collect()
+}
+
+/// Draws an independent RNG for each of `n` items from `rng`.
+pub(crate) fn item_rngs(rng: &mut (impl RngCore + CryptoRng), n: usize) -> Vec<ChaCha20Rng> {
+    (0..n)
+        .map(|_| {
+            let mut seed = [0u8; 32];
+            rng.fill_bytes(&mut seed);
+            ChaCha20Rng::from_seed(seed)
+        })
+        .collect()
+}
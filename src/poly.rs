@@ -46,7 +46,7 @@ impl ScalarPoly {
     }
 }
 
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Serialize, PartialEq)]
 pub struct PointPoly(Vec<Point<Normal, Public, NonZero>>);
 
 impl PointPoly {
@@ -66,6 +66,10 @@ impl PointPoly {
         self.0.len()
     }
 
+    pub fn new(points: Vec<Point<Normal, Public, NonZero>>) -> Self {
+        Self(points)
+    }
+
     pub fn points(&self) -> &[Point<Normal, Public, NonZero>] {
         &self.0
     }
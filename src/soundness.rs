@@ -0,0 +1,390 @@
+//! The exact soundness of the cut-and-choose and an optimizer built on it.
+//!
+//! Alice makes `M` commitments, Bob opens `n_openings` of them at random and the remaining `N·B`
+//! are shuffled into `N` buckets of `B`. If Alice cheats on `t` commitments she gets away with it
+//! when none of them are opened and at least one bucket is made up of only bad ones (since then
+//! Bob has no honest encryption for that bucket). The chance of that for a given `t` is
+//!
+//! `P(t) = F(t) / C(M, t)`
+//!
+//! where `F(t)` counts the ways of putting `t` bad commitments into the `N·B` closed slots so that
+//! some bucket is full of them. Alice picks the `t` that maximizes `P(t)`.
+//!
+//! We compute `F(t)` by going through the buckets one at a time keeping track of how many of the
+//! placements so far have a full bucket and how many don't. Every step only adds positive numbers
+//! so unlike inclusion-exclusion there's no cancellation to lose precision to. The counts are
+//! divided by `C(k·B, t)` as we go so they stay in `[0, 1]`.
+use crate::common::CostWeights;
+
+/// `-log2` of the probability that a cheating Alice gets a bucket with no honest encryption past
+/// Bob when he opens `n_openings` of `M` commitments and puts the rest into `n_buckets` buckets of
+/// `bucket_size`.
+///
+/// # Panics
+///
+/// If `M != n_buckets * bucket_size + n_openings`.
+pub fn soundness_bits(M: usize, n_buckets: usize, bucket_size: usize, n_openings: usize) -> f64 {
+    assert_eq!(
+        M,
+        n_buckets * bucket_size + n_openings,
+        "every commitment is either opened or in a bucket"
+    );
+    let ln_factorials = LnFactorials::new(M);
+    let cap = max_useful_cheats(&ln_factorials, M, n_buckets, bucket_size);
+    let full = full_bucket_ratios(&ln_factorials, n_buckets, bucket_size, cap);
+    soundness_bits_with(&ln_factorials, M, n_buckets, bucket_size, &full)
+}
+
+/// The most commitments [`optimal_cut_and_choose`] will have Bob open for each one left closed.
+/// Opening more is never going to be the cheapest and contracts that ask for it are rejected since
+/// they would have Alice make an enormous number of commitments.
+pub const MAX_OPENINGS_PER_CLOSED: usize = 64;
+
+/// The cut-and-choose parameters chosen by [`optimal_cut_and_choose`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CutAndChoose {
+    pub bucket_size: u8,
+    pub n_openings: usize,
+    pub soundness_bits: f64,
+}
+
+impl CutAndChoose {
+    pub fn M(&self, n_buckets: usize) -> usize {
+        n_buckets * self.bucket_size as usize + self.n_openings
+    }
+}
+
+/// Finds the bucket size and number of openings with at least `security_param` bits of soundness
+/// (according to [`soundness_bits`]) that cost the least according to `weights`. Returns `None` if
+/// no bucket size up to `u8::MAX` is enough.
+pub fn optimal_cut_and_choose(
+    security_param: u8,
+    n_buckets: usize,
+    weights: &CostWeights,
+) -> Option<CutAndChoose> {
+    assert!(n_buckets > 0);
+    let mut best: Option<(u64, CutAndChoose)> = None;
+
+    // with some weights bigger buckets keep getting cheaper so we have to stop somewhere
+    for bucket_size in 1..=u8::MAX {
+        let n_closed = n_buckets * bucket_size as usize;
+        let too_expensive = |n_openings: usize| {
+            n_openings > MAX_OPENINGS_PER_CLOSED * n_closed
+                || matches!(best, Some((best_cost, _))
+                    if weights.cost(n_closed + n_openings, n_openings) >= best_cost)
+        };
+        // bigger buckets only cost more
+        if too_expensive(1) {
+            break;
+        }
+
+        // more openings only make it more sound so search for the fewest that are enough
+        let mut openings = OpeningsSearch::new(n_buckets, bucket_size as usize, security_param);
+        let mut lo = 1;
+        let mut hi = 1;
+        loop {
+            if too_expensive(hi) || openings.are_enough(hi) {
+                break;
+            }
+            lo = hi + 1;
+            hi *= 2;
+        }
+        if too_expensive(hi) {
+            continue;
+        }
+        while lo < hi {
+            let mid = (lo + hi) / 2;
+            if openings.are_enough(mid) {
+                hi = mid;
+            } else {
+                lo = mid + 1;
+            }
+        }
+
+        let candidate = CutAndChoose {
+            bucket_size,
+            n_openings: lo,
+            soundness_bits: soundness_bits(n_closed + lo, n_buckets, bucket_size as usize, lo),
+        };
+        best = Some((weights.cost(n_closed + lo, lo), candidate));
+    }
+
+    best.map(|(_, chosen)| chosen)
+}
+
+/// Decides whether a number of openings gives enough soundness for one bucket size.
+///
+/// Counting `F(t)` exactly takes a while for big `t` so we first try the bounds
+/// `N·C(N·B - B, t - B) - C(N, 2)·C(N·B - 2B, t - 2B) ≤ F(t) ≤ N·C(N·B - B, t - B)` (from
+/// inclusion-exclusion) which are tight when `P(t)` is small. Only the `t` where the upper bound
+/// could be above `2^-s` matter at all.
+struct OpeningsSearch {
+    n_buckets: usize,
+    bucket_size: usize,
+    security_param: f64,
+    ln_factorials: LnFactorials,
+    full: Vec<f64>,
+}
+
+impl OpeningsSearch {
+    fn new(n_buckets: usize, bucket_size: usize, security_param: u8) -> Self {
+        OpeningsSearch {
+            n_buckets,
+            bucket_size,
+            security_param: security_param as f64,
+            ln_factorials: LnFactorials::new(n_buckets * bucket_size),
+            full: vec![],
+        }
+    }
+
+    fn are_enough(&mut self, n_openings: usize) -> bool {
+        let (N, B) = (self.n_buckets, self.bucket_size);
+        let n_closed = N * B;
+        let M = n_closed + n_openings;
+        self.ln_factorials.extend(M);
+        let ln_factorials = &self.ln_factorials;
+        let is_sound =
+            |ln_probability: f64| -ln_probability / std::f64::consts::LN_2 >= self.security_param;
+        let ln_n = (N as f64).ln();
+        let ln_pairs = ln_factorials.ln_binomial(N, 2.min(N));
+
+        let mut cap = None;
+        for t in B..=n_closed {
+            let ln_single = ln_factorials.ln_binomial(n_closed - B, t - B);
+            let ln_upper = ln_n + ln_single - ln_factorials.ln_binomial(M, t);
+            if is_sound(ln_upper) {
+                continue;
+            }
+            cap = Some(t);
+            let ln_lower = if N >= 2 && t >= 2 * B {
+                let ratio = (ln_pairs + ln_factorials.ln_binomial(n_closed - 2 * B, t - 2 * B)
+                    - ln_n
+                    - ln_single)
+                    .exp();
+                // when the second term is bigger the bound says nothing
+                if ratio < 1.0 {
+                    ln_upper + (-ratio).ln_1p()
+                } else {
+                    f64::NEG_INFINITY
+                }
+            } else {
+                ln_upper
+            };
+            if !is_sound(ln_lower) {
+                return false;
+            }
+        }
+        let cap = match cap {
+            Some(cap) => cap,
+            None => return true,
+        };
+
+        // the bounds weren't enough so count exactly
+        if self.full.len() <= cap {
+            self.full = full_bucket_ratios(ln_factorials, N, B, cap);
+        }
+        (B..=cap).filter(|&t| self.full[t] > 0.0).all(|t| {
+            is_sound(
+                self.full[t].ln() + ln_factorials.ln_binomial(n_closed, t)
+                    - ln_factorials.ln_binomial(M, t),
+            )
+        })
+    }
+}
+
+/// The largest `t` worth considering. `F(t) ≤ N·C(N·B - B, t - B)` (a union bound over which
+/// bucket is full) so once that makes `P(t)` smaller than `P(B)` (which is exact) `t` can't be
+/// Alice's best choice.
+fn max_useful_cheats(
+    ln_factorials: &LnFactorials,
+    M: usize,
+    n_buckets: usize,
+    bucket_size: usize,
+) -> usize {
+    let n_closed = n_buckets * bucket_size;
+    let ln_single = (n_buckets as f64).ln() - ln_factorials.ln_binomial(M, bucket_size);
+    (bucket_size..=n_closed)
+        .rev()
+        .find(|&t| {
+            let ln_bound = (n_buckets as f64).ln()
+                + ln_factorials.ln_binomial(n_closed - bucket_size, t - bucket_size)
+                - ln_factorials.ln_binomial(M, t);
+            ln_bound >= ln_single
+        })
+        .unwrap_or(bucket_size)
+}
+
+/// `F(t) / C(N·B, t)` for `t` in `0..=cap`: the probability that `t` bad commitments shuffled into
+/// the buckets fill at least one of them.
+fn full_bucket_ratios(
+    ln_factorials: &LnFactorials,
+    n_buckets: usize,
+    bucket_size: usize,
+    cap: usize,
+) -> Vec<f64> {
+    let B = bucket_size;
+    // after k buckets: the probability that u bad ones among k·B slots fill a bucket / don't
+    let mut full = vec![0.0; cap + 1];
+    let mut not_full = vec![0.0; cap + 1];
+    not_full[0] = 1.0;
+    for k in 1..=n_buckets {
+        let slots = k * B;
+        let mut next_full = vec![0.0; cap + 1];
+        let mut next_not_full = vec![0.0; cap + 1];
+        for u in 0..=cap.min(slots) {
+            let ln_total = ln_factorials.ln_binomial(slots, u);
+            // b of the u bad ones land in bucket k
+            for b in u.saturating_sub(slots - B)..=B.min(u) {
+                let weight = (ln_factorials.ln_binomial(B, b)
+                    + ln_factorials.ln_binomial(slots - B, u - b)
+                    - ln_total)
+                    .exp();
+                next_full[u] += weight * full[u - b];
+                if b == B {
+                    next_full[u] += weight * not_full[u - b];
+                } else {
+                    next_not_full[u] += weight * not_full[u - b];
+                }
+            }
+        }
+        full = next_full;
+        not_full = next_not_full;
+    }
+    full
+}
+
+fn soundness_bits_with(
+    ln_factorials: &LnFactorials,
+    M: usize,
+    n_buckets: usize,
+    bucket_size: usize,
+    full: &[f64],
+) -> f64 {
+    let n_closed = n_buckets * bucket_size;
+    let cap = max_useful_cheats(ln_factorials, M, n_buckets, bucket_size);
+    assert!(cap < full.len());
+    let ln_max = (bucket_size..=cap)
+        .filter(|&t| full[t] > 0.0)
+        .map(|t| {
+            full[t].ln() + ln_factorials.ln_binomial(n_closed, t) - ln_factorials.ln_binomial(M, t)
+        })
+        .fold(f64::NEG_INFINITY, f64::max);
+    -ln_max / std::f64::consts::LN_2
+}
+
+/// `ln(n!)` for `n` up to some limit so binomials can be computed in log space.
+struct LnFactorials(Vec<f64>);
+
+impl LnFactorials {
+    fn new(n: usize) -> Self {
+        let mut ln_factorials = LnFactorials(vec![0.0]);
+        ln_factorials.extend(n);
+        ln_factorials
+    }
+
+    fn extend(&mut self, n: usize) {
+        while self.0.len() <= n {
+            let i = self.0.len();
+            self.0.push(self.0[i - 1] + (i as f64).ln());
+        }
+    }
+
+    fn ln_binomial(&self, n: usize, k: usize) -> f64 {
+        self.0[n] - self.0[k] - self.0[n - k]
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Counts every way of cheating for a tiny cut-and-choose by brute force.
+    fn brute_force_bits(n_buckets: usize, bucket_size: usize, n_openings: usize) -> f64 {
+        let M = n_buckets * bucket_size + n_openings;
+        let binomial = |n: usize, k: usize| -> f64 {
+            (0..k).map(|i| (n - i) as f64 / (i + 1) as f64).product()
+        };
+        let max = (0..=n_buckets * bucket_size)
+            .map(|t| {
+                // count the t-subsets of the closed slots (slot i is in bucket i / B) that fill a
+                // bucket
+                let full = (0u32..1 << (n_buckets * bucket_size))
+                    .filter(|set| set.count_ones() as usize == t)
+                    .filter(|set| {
+                        (0..n_buckets).any(|bucket| {
+                            let mask = ((1 << bucket_size) - 1) << (bucket * bucket_size);
+                            set & mask == mask
+                        })
+                    })
+                    .count();
+                full as f64 / binomial(M, t)
+            })
+            .fold(0.0, f64::max);
+        -max.log2()
+    }
+
+    #[test]
+    fn matches_brute_force() {
+        for (n_buckets, bucket_size, n_openings) in [
+            (1, 1, 1),
+            (1, 3, 2),
+            (2, 2, 3),
+            (3, 2, 4),
+            (4, 3, 5),
+            (2, 5, 1),
+        ] {
+            let M = n_buckets * bucket_size + n_openings;
+            let exact = soundness_bits(M, n_buckets, bucket_size, n_openings);
+            let brute_force = brute_force_bits(n_buckets, bucket_size, n_openings);
+            assert!(
+                (exact - brute_force).abs() < 1e-9,
+                "N: {} B: {} openings: {} exact: {} brute force: {}",
+                n_buckets,
+                bucket_size,
+                n_openings,
+                exact,
+                brute_force
+            );
+        }
+        // with no openings Alice can cheat on everything
+        assert_eq!(soundness_bits(6, 3, 2, 0), 0.0);
+    }
+
+    #[test]
+    fn optimizer_meets_the_security_param() {
+        let weights = CostWeights::default();
+        for (s, n_buckets) in [(20, 1), (30, 14), (40, 42)] {
+            let chosen = optimal_cut_and_choose(s, n_buckets, &weights).unwrap();
+            assert!(chosen.soundness_bits >= s as f64);
+            let M = chosen.M(n_buckets);
+            assert_eq!(
+                soundness_bits(M, n_buckets, chosen.bucket_size as usize, chosen.n_openings),
+                chosen.soundness_bits
+            );
+            // one fewer opening isn't enough
+            let fewer = soundness_bits(
+                M - 1,
+                n_buckets,
+                chosen.bucket_size as usize,
+                chosen.n_openings - 1,
+            );
+            assert!(fewer < s as f64);
+        }
+    }
+
+    #[test]
+    fn optimizer_stops_at_the_biggest_bucket() {
+        // openings cost so much more than anything else that bigger buckets would only start to
+        // cost more once they were millions of commitments long
+        let weights = CostWeights {
+            any: 1e-6,
+            open: 5.0,
+            closed: 1e-6,
+        };
+        assert!(weights.validate().is_ok());
+        // with one bucket a single opening would need buckets of about 2^30 so every bigger
+        // bucket looks like it could still be cheaper
+        let chosen = optimal_cut_and_choose(30, 1, &weights).unwrap();
+        assert!(chosen.soundness_bits >= 30.0);
+    }
+}